@@ -56,9 +56,14 @@ impl fmt::Display for Expr {
                 left,
                 operator,
                 right,
+                operand_lines: _,
             } => {
                 write!(f, "({operator} {left} {right})")
             }
+            ExprKind::BlockExpr {
+                statements: _,
+                value,
+            } => write!(f, "(block {value})"),
             ExprKind::Call {
                 callee,
                 paren: _,
@@ -75,6 +80,7 @@ impl fmt::Display for Expr {
                 write!(f, ")")
             }
             ExprKind::Get { object, name } => write!(f, "{object}.{name}"),
+            ExprKind::OptionalGet { object, name } => write!(f, "{object}?.{name}"),
 
             ExprKind::Grouping { expression } => {
                 write!(f, "(group {expression})")
@@ -87,7 +93,7 @@ impl fmt::Display for Expr {
                 operator,
                 right,
             } => {
-                write!(f, "{left} {operator} {right}")
+                write!(f, "({operator} {left} {right})")
             }
             ExprKind::Set {
                 object,
@@ -108,3 +114,24 @@ impl fmt::Display for Expr {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser, scanner::Scanner, stmt::Stmt};
+
+    fn display(expression: &str) -> String {
+        let (tokens, had_error) = Scanner::new(&format!("{expression};")).scan_tokens();
+        assert!(!had_error);
+        match &parser::parse(tokens, None).unwrap_or_else(|_| panic!("parse error"))[..] {
+            [Stmt::Expression { expression }] => expression.to_string(),
+            _ => panic!("expected an expression statement"),
+        }
+    }
+
+    #[test]
+    fn logical_operators_show_their_grouping() {
+        assert_eq!(display("a and b or c"), "(or (and a b) c)");
+        assert_eq!(display("a or b and c"), "(or a (and b c))");
+        assert_eq!(display("a and b == c"), "(and a (== b c))");
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{
+    expr::Expr,
+    stmt::Stmt,
+    visit::{walk_expr, walk_stmt, walk_stmts, Visitor},
+};
+
+/// How many of each kind of node a parsed program contains.
+///
+/// Nested nodes are counted too, and methods count as functions.
+#[derive(Default)]
+pub struct AstStats {
+    pub statements: usize,
+    pub functions: usize,
+    pub classes: usize,
+    pub expressions: usize,
+}
+
+impl AstStats {
+    pub fn count(statements: &[Stmt]) -> Self {
+        let mut stats = AstStats::default();
+        walk_stmts(&mut stats, statements);
+        stats
+    }
+}
+
+impl Visitor for AstStats {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.statements += 1;
+        match stmt {
+            Stmt::Class { methods, .. } => {
+                self.classes += 1;
+                self.functions += methods.len();
+            }
+            Stmt::Function(_) => self.functions += 1,
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.expressions += 1;
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, scanner::Scanner};
+
+    #[test]
+    fn counts_nested_nodes() {
+        let source = "fun f(x) { return x + 1; }\n\
+                      class A { m() { print f(2); } n() {} }\n\
+                      { var a = f(3); }\n";
+        let (tokens, had_error) = Scanner::new(source).scan_tokens();
+        assert!(!had_error);
+        let statements = parser::parse(tokens, None).unwrap_or_else(|_| panic!("parse error"));
+
+        let stats = AstStats::count(&statements);
+        // f, its return, A, m's print, the block and its var
+        assert_eq!(stats.statements, 6);
+        // f and both methods
+        assert_eq!(stats.functions, 3);
+        assert_eq!(stats.classes, 1);
+        // x + 1 and its operands, the two calls with their callees and arguments
+        assert_eq!(stats.expressions, 9);
+    }
+}
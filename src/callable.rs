@@ -1,21 +1,29 @@
 use std::{
     collections::HashMap,
+    io::{stdin, stdout, Write},
+    rc::Rc,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    class::Class,
+    class::{Class, Fields},
     environment::Environment,
     instance::Instance,
-    interpreter::execute_statements,
+    interpreter::{
+        eval, evaluate, execute_statements, is_equal, repr, stringify, TailCall, Unwind,
+    },
     stmt,
     token::{Literal, Token},
-    utils::Soo,
+    utils::{localize, Message, Soo},
 };
 
 #[derive(Clone, Debug)]
 pub struct Callable {
     pub arity: usize,
+    /// how many of the trailing parameters have default values and may be omitted
+    pub optional: usize,
+    /// accepts any number of arguments beyond `arity`
+    pub variadic: bool,
     pub parameters: Vec<String>,
     pub kind: CallableKind,
 }
@@ -39,10 +47,16 @@ impl Callable {
     ) -> Self {
         Callable {
             arity: declaration.params.len(),
+            optional: declaration
+                .defaults
+                .iter()
+                .filter(|default| default.is_some())
+                .count(),
+            variadic: false,
             parameters: declaration
                 .params
                 .iter()
-                .map(|token| token.lexeme.to_owned())
+                .map(|token| token.lexeme.to_string())
                 .collect(),
             kind: CallableKind::Function {
                 declaration: Box::new(declaration.clone()),
@@ -56,21 +70,83 @@ impl Callable {
         name: String,
         superclass: Option<crate::class::Class>,
         methods: HashMap<String, Callable>,
+        fields: Option<Rc<Fields>>,
     ) -> Self {
+        let class = Class::new(name, superclass, methods, fields);
+        // a class without its own `init` is constructed with the one it inherits
+        let initializer = class.find_method("init");
         Callable {
-            arity: methods.get("init").map(|f| f.arity).unwrap_or(0),
+            arity: initializer.as_ref().map(|f| f.arity).unwrap_or(0),
+            optional: initializer.as_ref().map(|f| f.optional).unwrap_or(0),
+            variadic: false,
             parameters: Vec::new(),
-            kind: CallableKind::Class(Class::new(name, superclass, methods)),
+            kind: CallableKind::Class(class),
+        }
+    }
+
+    /// a final parameter named `...` marks the native as variadic
+    pub fn new_native(name: &'static str, parameters: &[&str]) -> Self {
+        let variadic = parameters.last() == Some(&"...");
+        Callable {
+            arity: parameters.len() - variadic as usize,
+            optional: 0,
+            variadic,
+            parameters: parameters.iter().map(|param| param.to_string()).collect(),
+            kind: CallableKind::Native(name),
         }
     }
 
-    pub fn call(self, arguments: Vec<Literal>, token: &Token) -> Result<Literal, (Token, Soo)> {
+    /// check that a call supplies an acceptable number of arguments
+    pub fn check_arity(&self, count: usize) -> Result<(), Soo> {
+        let required = self.arity - self.optional;
+        let expected = if self.variadic {
+            format!("at least {required}")
+        } else if self.optional > 0 {
+            format!("{required} to {}", self.arity)
+        } else {
+            self.arity.to_string()
+        };
+
+        if count < required || (!self.variadic && count > self.arity) {
+            let message = localize(Message::ArgumentCount, &[&expected, &count]);
+            match &self.kind {
+                CallableKind::Class(class) if self.arity == 0 => Err(format!(
+                    "{message} Class '{}' has no initializer that takes arguments.",
+                    class.name
+                )
+                .into()),
+                _ => Err(message),
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn call(
+        self,
+        arguments: Vec<Literal>,
+        token: &Token,
+        environment: &mut Environment,
+    ) -> Result<Literal, (Token, Soo)> {
+        // constructing an instance isn't counted, only the initializer it runs
+        if let Some(mut stats) = environment.stats_mut()
+            && !matches!(self.kind, CallableKind::Class(_))
+        {
+            stats.calls += 1;
+        }
+
         match self.kind {
             CallableKind::Class(class) => {
-                let mut instance = Instance::new(class);
+                let instance = Instance::new(class);
+                // field initializers run Lox code, so they get a frame like a function body
+                environment
+                    .push_frame(&instance.class.name, token.line)
+                    .map_err(|message| (token.clone(), message))?;
+                initialize_fields(&instance.class, &instance)?;
+                environment.call_stack.borrow_mut().pop();
                 if let Some(mut initializer) = instance.class.find_method("init") {
                     initializer.bind(instance.clone());
-                    initializer.call(arguments, token)?;
+                    initializer.call(arguments, token, environment)?;
                 }
 
                 Ok(Literal::InstanceLiteral(instance))
@@ -80,36 +156,122 @@ impl Callable {
                 mut closure,
                 is_initializer,
             } => {
-                closure.add_scope();
-                for (param, arg) in self.parameters.iter().zip(arguments.into_iter()) {
-                    closure.define(param, arg);
-                }
+                // the frame is left in place when an error propagates so it can be reported
+                closure
+                    .push_frame(&declaration.name.lexeme, token.line)
+                    .map_err(|message| (token.clone(), message))?;
 
-                match execute_statements(&mut declaration.body, &mut closure) {
-                    Err((token, message)) => {
-                        return match (token.typ, token.lexeme.as_str()) {
-                            (crate::token_type::TokenType::Return, "RETURN") => {
-                                if is_initializer {
-                                    Ok(closure.get_at(0, "this").unwrap())
-                                } else {
-                                    Ok(token.literal)
+                let mut arguments = arguments;
+                let result = loop {
+                    closure.add_scope();
+                    let supplied = arguments.len();
+                    for (param, arg) in self.parameters.iter().zip(arguments) {
+                        closure.define(param, arg);
+                    }
+
+                    let returned =
+                        run_body(&self.parameters, &mut declaration, supplied, &mut closure);
+                    closure.del_scope();
+                    match returned {
+                        Ok(()) => break Literal::None,
+                        Err(Unwind::Return(value, _)) => break value,
+                        Err(Unwind::TailCall(tail_call)) => {
+                            let TailCall {
+                                callee,
+                                arguments: tail_arguments,
+                                paren,
+                            } = *tail_call;
+                            callee
+                                .check_arity(tail_arguments.len())
+                                .map_err(|message| (paren.clone(), message))?;
+
+                            if callee.is_same_function(&declaration, &closure) {
+                                if let Some(mut stats) = closure.stats_mut() {
+                                    stats.calls += 1;
                                 }
+                                arguments = tail_arguments;
+                                continue;
                             }
-                            _ => Err((token, message)),
+                            // the name was reassigned to something else, so make an ordinary call
+                            break callee.call(tail_arguments, &paren, &mut closure)?;
                         }
+                        Err(Unwind::Error(token, message)) => return Err((token, message)),
                     }
-                    _ => {}
                 };
 
-                closure.del_scope();
+                closure.call_stack.borrow_mut().pop();
 
+                // an initializer produces its instance, even after a bare `return;`
                 if is_initializer {
                     Ok(closure.get_at(0, "this").unwrap())
                 } else {
-                    Ok(Literal::None)
+                    Ok(result)
                 }
             }
             CallableKind::Native(name) => match name {
+                "abs" => match arguments.first().unwrap() {
+                    Literal::F64(n) => Ok(Literal::F64(n.abs())),
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'abs' accepts a single number.".into(),
+                    )),
+                },
+                "assert_eq" => {
+                    let mut arguments = arguments.into_iter();
+                    let (expected, actual) = (arguments.next().unwrap(), arguments.next().unwrap());
+                    if is_equal(expected.clone(), actual.clone()) {
+                        Ok(Literal::None)
+                    } else {
+                        Err((
+                            token.clone(),
+                            format!(
+                                "Assertion failed: expected {} but got {}.",
+                                stringify(expected, environment)?,
+                                stringify(actual, environment)?
+                            )
+                            .into(),
+                        ))
+                    }
+                }
+                "bin" | "hex" | "oct" => match arguments.first().unwrap() {
+                    // `u64::MAX as f64` rounds up to 2^64, the first magnitude `as u64` clamps
+                    Literal::F64(n)
+                        if n.is_finite() && n.fract() == 0.0 && n.abs() < u64::MAX as f64 =>
+                    {
+                        let sign = if *n < 0.0 { "-" } else { "" };
+                        let magnitude = n.abs() as u64;
+                        Ok(Literal::StringLiteral(
+                            match name {
+                                "bin" => format!("{sign}0b{magnitude:b}"),
+                                "hex" => format!("{sign}0x{magnitude:x}"),
+                                _ => format!("{sign}0o{magnitude:o}"),
+                            }
+                            .into(),
+                        ))
+                    }
+                    Literal::F64(n) if n.is_finite() && n.fract() == 0.0 => Err((
+                        token.clone(),
+                        format!("'{name}' only accepts numbers smaller in magnitude than 2^64.")
+                            .into(),
+                    )),
+                    _ => Err((
+                        token.clone(),
+                        format!("Invalid function arguments, '{name}' accepts a whole number.")
+                            .into(),
+                    )),
+                },
+                "chr" => match arguments.first().unwrap() {
+                    Literal::F64(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64 => {
+                        match char::from_u32(*n as u32) {
+                            Some(c) => Ok(Literal::StringLiteral(c.to_string().into())),
+                            _ => Err((token.clone(), "Invalid code point.".into())),
+                        }
+                    }
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'chr' accepts a code point.".into(),
+                    )),
+                },
                 "clock" => Ok(Literal::F64(
                     SystemTime::now()
                         .duration_since(UNIX_EPOCH)
@@ -117,11 +279,63 @@ impl Callable {
                         .as_millis() as f64
                         / 1000.0,
                 )),
+                // instances get their own copy of the fields, all other values are
+                // immutable so returning them as-is is already a copy
+                "clone" => match arguments.into_iter().next().unwrap() {
+                    Literal::InstanceLiteral(instance) => {
+                        Ok(Literal::InstanceLiteral(instance.shallow_copy()))
+                    }
+                    value => Ok(value),
+                },
+                "eprint" => {
+                    let message = stringify(arguments.into_iter().next().unwrap(), environment)?;
+                    eprintln!("{message}");
+                    Ok(Literal::None)
+                }
+                "eval" => match arguments.into_iter().next().unwrap() {
+                    Literal::StringLiteral(source) => eval(&source, token, environment),
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'eval' accepts a single string.".into(),
+                    )),
+                },
+                "format" => {
+                    let mut arguments = arguments.into_iter();
+                    let template =
+                        match arguments.next().unwrap() {
+                            Literal::StringLiteral(s) => s,
+                            _ => return Err((
+                                token.clone(),
+                                "Invalid function arguments, 'format' expects a template string."
+                                    .into(),
+                            )),
+                        };
+
+                    let pieces: Vec<&str> = template.split("{}").collect();
+                    if pieces.len() - 1 != arguments.len() {
+                        return Err((
+                            token.clone(),
+                            format!(
+                                "Template has {} placeholders but got {} arguments.",
+                                pieces.len() - 1,
+                                arguments.len()
+                            )
+                            .into(),
+                        ));
+                    }
+
+                    let mut result = pieces[0].to_owned();
+                    for (piece, argument) in pieces[1..].iter().zip(arguments) {
+                        result.push_str(&stringify(argument, environment)?);
+                        result.push_str(piece);
+                    }
+                    Ok(Literal::StringLiteral(result.into()))
+                }
                 "getchar" => match &arguments[..] {
                     [Literal::StringLiteral(s), Literal::F64(i)] => {
                         if i.fract() == 0.0 && *i >= 0.0 {
                             match s.chars().nth(*i as usize) {
-                                Some(c) => Ok(Literal::StringLiteral(c.to_string())),
+                                Some(c) => Ok(Literal::StringLiteral(c.to_string().into())),
                                 _ => Err((token.clone(), "String index out of range.".into())),
                             }
                         } else {
@@ -149,11 +363,157 @@ impl Callable {
                             .into(),
                     )),
                 },
+                "is_bool" | "is_callable" | "is_instance" | "is_integer" | "is_nil"
+                | "is_number" | "is_string" => {
+                    let value = arguments.first().unwrap();
+                    Ok(Literal::BoolLiteral(match name {
+                        "is_bool" => matches!(value, Literal::BoolLiteral(_)),
+                        "is_callable" => matches!(value, Literal::CallableLiteral(_)),
+                        "is_instance" => matches!(value, Literal::InstanceLiteral(_)),
+                        "is_integer" => {
+                            matches!(value, Literal::F64(n) if n.is_finite() && n.fract() == 0.0)
+                        }
+                        "is_nil" => matches!(value, Literal::None),
+                        "is_number" => matches!(value, Literal::F64(_)),
+                        _ => matches!(value, Literal::StringLiteral(_)),
+                    }))
+                }
+                // Euclidean remainder, so the result is never negative: mod(-7, 3) is 2
+                // where a truncating remainder would give -1
+                "mod" => match &arguments[..] {
+                    [Literal::F64(_), Literal::F64(d)] if *d == 0.0 => {
+                        Err((token.clone(), "Modulo by zero.".into()))
+                    }
+                    [Literal::F64(n), Literal::F64(d)] => Ok(Literal::F64(n.rem_euclid(*d))),
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'mod' accepts two numbers.".into(),
+                    )),
+                },
+                // unlike `clock` this counts from interpreter start, not the Unix epoch
+                "nanotime" => Ok(Literal::F64(environment.started.elapsed().as_nanos() as f64)),
+                "ord" => match arguments.first().unwrap() {
+                    Literal::StringLiteral(s) => {
+                        let mut chars = s.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => Ok(Literal::F64(c as u32 as f64)),
+                            _ => Err((
+                                token.clone(),
+                                "'ord' expects a string of exactly one character.".into(),
+                            )),
+                        }
+                    }
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'ord' accepts a single string.".into(),
+                    )),
+                },
+                "panic" => {
+                    let message = stringify(arguments.into_iter().next().unwrap(), environment)?;
+                    Err((token.clone(), message.into()))
+                }
+                // a negative base only has a real power when the exponent is a whole
+                // number, so pow(-8, 1/3) is an error rather than a silent NaN
+                "pow" => match &arguments[..] {
+                    [Literal::F64(base), Literal::F64(exponent)]
+                        if *base < 0.0 && exponent.fract() != 0.0 =>
+                    {
+                        Err((
+                            token.clone(),
+                            "'pow' of a negative base with a fractional exponent is undefined."
+                                .into(),
+                        ))
+                    }
+                    [Literal::F64(base), Literal::F64(exponent)] => {
+                        Ok(Literal::F64(base.powf(*exponent)))
+                    }
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'pow' accepts two numbers.".into(),
+                    )),
+                },
+                "read_number" => {
+                    // anything printed so far, like a prompt, must show before waiting
+                    let _ = stdout().flush();
+                    let mut line = String::new();
+                    match stdin().read_line(&mut line) {
+                        Ok(0) => Err((token.clone(), "Unexpected end of input.".into())),
+                        Ok(_) => match line.trim().parse::<f64>() {
+                            Ok(f) => Ok(Literal::F64(f)),
+                            Err(_) => {
+                                Err((token.clone(), "Unable to parse input as a number.".into()))
+                            }
+                        },
+                        Err(_) => Err((token.clone(), "Unable to read input.".into())),
+                    }
+                }
+                "repr" => Ok(Literal::StringLiteral(
+                    repr(arguments.first().unwrap(), environment).into(),
+                )),
+                "sign" => match arguments.first().unwrap() {
+                    Literal::F64(n) if *n > 0.0 => Ok(Literal::F64(1.0)),
+                    Literal::F64(n) if *n < 0.0 => Ok(Literal::F64(-1.0)),
+                    // zero of either sign gives 0, NaN stays NaN
+                    Literal::F64(n) => Ok(Literal::F64(n.abs())),
+                    _ => Err((
+                        token.clone(),
+                        "Invalid function arguments, 'sign' accepts a single number.".into(),
+                    )),
+                },
+                "trace" => {
+                    let value = arguments.into_iter().next().unwrap();
+                    eprintln!("[trace] {}", stringify(value.clone(), environment)?);
+                    Ok(value)
+                }
                 _ => unimplemented!("Native function '{}' has not been implemented", name),
             },
         }
     }
 
+    /// natives are equal when they have the same name, classes when they come from
+    /// the same run of a class statement, and functions when they come from the same
+    /// declaration, whatever they close over
+    pub fn is_equal(&self, other: &Callable) -> bool {
+        match (&self.kind, &other.kind) {
+            (CallableKind::Class(a), CallableKind::Class(b)) => a.is_same_class(b),
+            (
+                CallableKind::Function {
+                    declaration: a,
+                    closure: _,
+                    is_initializer: _,
+                },
+                CallableKind::Function {
+                    declaration: b,
+                    closure: _,
+                    is_initializer: _,
+                },
+            ) => same_declaration(a, b),
+            (CallableKind::Native(a), CallableKind::Native(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// whether this is `declaration` closing over `closure`, rather than another
+    /// function or another closure created from the same declaration
+    fn is_same_function(&self, declaration: &stmt::Function, closure: &Environment) -> bool {
+        match &self.kind {
+            CallableKind::Function {
+                declaration: other,
+                closure: other_closure,
+                is_initializer: _,
+            } => {
+                same_declaration(other, declaration)
+                    && other_closure.layers.len() == closure.layers.len()
+                    && other_closure
+                        .layers
+                        .iter()
+                        .zip(&closure.layers)
+                        .all(|(a, b)| Rc::ptr_eq(a, b))
+            }
+            _ => false,
+        }
+    }
+
     pub fn bind(&mut self, instance: Instance) {
         match &mut self.kind {
             CallableKind::Function {
@@ -168,3 +528,57 @@ impl Callable {
         }
     }
 }
+
+/// evaluate the defaults of the parameters a call left out, in the function's scope so
+/// they can use earlier parameters, then run the function's body
+fn run_body(
+    parameters: &[String],
+    declaration: &mut stmt::Function,
+    supplied: usize,
+    closure: &mut Environment,
+) -> Result<(), Unwind> {
+    for (param, default) in parameters
+        .iter()
+        .zip(declaration.defaults.iter_mut())
+        .skip(supplied)
+    {
+        if let Some(expr) = default {
+            let value = evaluate(expr, closure)?;
+            closure.define(param, value);
+        }
+    }
+    execute_statements(&mut declaration.body, closure)
+}
+
+/// set the fields declared by `class` on a new instance, after those declared
+/// by its superclasses so that a subclass can override their values
+fn initialize_fields(class: &Class, instance: &Instance) -> Result<(), (Token, Soo)> {
+    if let Some(superclass) = &class.superclass {
+        initialize_fields(superclass, instance)?;
+    }
+
+    if let Some(fields) = &class.fields {
+        let mut closure = fields.closure.clone();
+        closure.add_scope();
+        closure.define("this", Literal::InstanceLiteral(instance.clone()));
+
+        let mut instance = instance.clone();
+        for field in &fields.declarations {
+            let value = match &field.initializer {
+                Some(expr) => {
+                    evaluate(&mut expr.clone(), &mut closure).map_err(Unwind::into_error)?
+                }
+                _ => Literal::None,
+            };
+            instance.set(&field.name, value);
+        }
+    }
+
+    Ok(())
+}
+
+/// declarations are told apart by their parse-time id, since each closure holds its
+/// own copy of the declaration
+fn same_declaration(a: &stmt::Function, b: &stmt::Function) -> bool {
+    a.id == b.id
+}
@@ -1,12 +1,24 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
-use crate::callable::Callable;
+use crate::{callable::Callable, environment::Environment, stmt::Field};
+
+/// fields declared in a class body and the environment their initializers run in
+#[derive(Clone, Debug)]
+pub struct Fields {
+    pub declarations: Vec<Field>,
+    pub closure: Environment,
+}
 
 #[derive(Clone, Debug)]
 pub struct Class {
     pub name: String,
     pub superclass: Option<Box<Class>>,
     pub methods: HashMap<String, Callable>,
+    /// shared, so copies of the class don't copy the environment field initializers run in
+    pub fields: Option<Rc<Fields>>,
+    /// shared by copies of the class and by nothing else, so each run of a class
+    /// statement makes a distinct class
+    identity: Rc<()>,
 }
 
 impl Class {
@@ -14,18 +26,25 @@ impl Class {
         name: String,
         superclass: Option<Class>,
         methods: HashMap<String, Callable>,
+        fields: Option<Rc<Fields>>,
     ) -> Self {
         Class {
             name,
             superclass: superclass.map(|c| Box::new(c)),
             methods,
+            fields,
+            identity: Rc::new(()),
         }
     }
 
-    pub fn find_method(&mut self, name: &str) -> Option<Callable> {
+    pub fn is_same_class(&self, other: &Class) -> bool {
+        Rc::ptr_eq(&self.identity, &other.identity)
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Callable> {
         let mut method = self.methods.get(name).map(|method| method.to_owned());
         if method.is_none() {
-            if let Some(superclass) = &mut self.superclass {
+            if let Some(superclass) = &self.superclass {
                 method = superclass.find_method(name);
             }
         }
@@ -1,59 +1,175 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{RefCell, RefMut},
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    rc::Rc,
+    time::Instant,
+};
 
 use crate::{
-    callable::{Callable, CallableKind},
+    callable::Callable,
     token::{Literal, Token},
-    utils::Soo,
+    utils::{localize, Message, Soo},
 };
 
+/// how deeply calls may nest before reporting a stack overflow, well within what
+/// `main::STACK_SIZE` holds in a debug build
+pub const MAX_CALL_DEPTH: usize = 2048;
+
+/// numeric constants predefined as globals, which programs may redefine
+const CONSTANTS: &[(&str, f64)] = &[("E", std::f64::consts::E), ("PI", std::f64::consts::PI)];
+
+/// native functions and their parameter names, which determine their arity
+const NATIVES: &[(&str, &[&str])] = &[
+    ("abs", &["n"]),
+    ("assert_eq", &["expected", "actual"]),
+    ("bin", &["n"]),
+    ("chr", &["code_point"]),
+    ("clock", &[]),
+    ("clone", &["value"]),
+    ("eprint", &["value"]),
+    ("eval", &["source"]),
+    ("format", &["template", "..."]),
+    ("getchar", &["s", "index"]),
+    ("hex", &["n"]),
+    ("int", &["n"]),
+    ("is_bool", &["value"]),
+    ("is_callable", &["value"]),
+    ("is_instance", &["value"]),
+    ("is_integer", &["value"]),
+    ("is_nil", &["value"]),
+    ("is_number", &["value"]),
+    ("is_string", &["value"]),
+    ("mod", &["n", "divisor"]),
+    ("nanotime", &[]),
+    ("oct", &["n"]),
+    ("ord", &["s"]),
+    ("panic", &["message"]),
+    ("pow", &["base", "exponent"]),
+    ("read_number", &[]),
+    ("repr", &["value"]),
+    ("sign", &["n"]),
+    ("trace", &["value"]),
+];
+
+/// execution counters reported by `--stats`
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub statements: usize,
+    pub calls: usize,
+    pub max_scope_depth: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct Environment {
-    pub layers: Vec<Rc<RefCell<HashMap<String, Literal>>>>,
+    /// variables of each scope, kept sorted so anything listing them is reproducible
+    pub layers: Vec<Rc<RefCell<BTreeMap<String, Literal>>>>,
     pub scopes: Vec<HashMap<String, bool>>,
-    pub locals: HashMap<usize, usize>,
+    /// scope distance of each resolved variable reference, shared by every closure
+    pub locals: Rc<RefCell<HashMap<usize, usize>>>,
+    /// ids of `return f(...)` calls where `f` names the function the return is in,
+    /// shared by every closure
+    pub tail_calls: Rc<RefCell<HashSet<usize>>>,
+    /// files being imported, innermost last, which relative imports start from
+    pub imports: Rc<RefCell<Vec<PathBuf>>>,
+    /// name and call line of each active function call, shared by every closure
+    pub call_stack: Rc<RefCell<Vec<(String, usize)>>>,
+    /// whether `+` converts a number to a string when the other operand is a string
+    pub coerce_strings: bool,
+    /// significant digits used when stringifying fractional numbers, `None` for full precision
+    pub precision: Option<usize>,
+    /// whether the resolver warns about locals shadowing an enclosing scope
+    pub warn_shadowing: bool,
+    /// counters shared by every closure, only collected when `--stats` is given
+    pub stats: Option<Rc<RefCell<Stats>>>,
+    /// when the interpreter started, the epoch of `nanotime`
+    pub started: Instant,
 }
 
 impl Environment {
     pub fn new() -> Self {
         let mut env = Environment {
-            layers: vec![Rc::new(RefCell::new(HashMap::new()))],
+            layers: vec![Rc::new(RefCell::new(BTreeMap::new()))],
             scopes: Vec::new(),
-            locals: HashMap::new(),
+            locals: Rc::new(RefCell::new(HashMap::new())),
+            tail_calls: Rc::new(RefCell::new(HashSet::new())),
+            imports: Rc::new(RefCell::new(Vec::new())),
+            call_stack: Rc::new(RefCell::new(Vec::new())),
+            coerce_strings: false,
+            precision: None,
+            warn_shadowing: false,
+            stats: None,
+            started: Instant::now(),
         };
 
-        // define native functions
-        env.define(
-            "clock",
-            Literal::CallableLiteral(Callable {
-                arity: 0,
-                parameters: Vec::new(),
-                kind: CallableKind::Native("clock"),
-            }),
-        );
-
-        env.define(
-            "getchar",
-            Literal::CallableLiteral(Callable {
-                arity: 2,
-                parameters: vec!["s".to_string(), "index".to_string()],
-                kind: CallableKind::Native("getchar"),
-            }),
-        );
-
-        env.define(
-            "int",
-            Literal::CallableLiteral(Callable {
-                arity: 1,
-                parameters: vec!["n".to_string()],
-                kind: CallableKind::Native("int"),
-            }),
-        );
+        for (name, value) in CONSTANTS {
+            env.define(name, Literal::F64(*value));
+        }
+        for (name, parameters) in NATIVES {
+            env.define(
+                name,
+                Literal::CallableLiteral(Callable::new_native(name, parameters)),
+            );
+        }
 
         env
     }
 
+    /// the number of scopes between a resolved expression and its variable's declaration
+    pub fn resolved_depth(&self, id: usize) -> Option<usize> {
+        self.locals.borrow().get(&id).copied()
+    }
+
+    /// the number of globals defined by the program under names that aren't predefined
+    pub fn user_global_count(&self) -> usize {
+        self.layers[0]
+            .borrow()
+            .keys()
+            .filter(|name| !Environment::is_predefined(name))
+            .count()
+    }
+
+    /// whether a global by this name, a native or a constant, exists before any code runs
+    pub fn is_predefined(name: &str) -> bool {
+        CONSTANTS.iter().any(|(constant, _)| *constant == name)
+            || NATIVES.iter().any(|(native, _)| *native == name)
+    }
+
+    /// a copy that only sees the globals, for running code as if it were top-level
+    pub fn globals_only(&self) -> Environment {
+        let mut globals = self.clone();
+        globals.layers.truncate(1);
+        globals
+    }
+
+    /// a copy with globals of its own, holding only the natives and constants
+    pub fn with_own_globals(&self) -> Environment {
+        let mut module = self.clone();
+        module.layers = Environment::new().layers;
+        module
+    }
+
+    /// record a call for backtraces, failing once calls nest too deeply
+    pub fn push_frame(&self, name: &str, line: usize) -> Result<(), Soo> {
+        let mut call_stack = self.call_stack.borrow_mut();
+        if call_stack.len() >= MAX_CALL_DEPTH {
+            return Err("Stack overflow.".into());
+        }
+        call_stack.push((name.to_owned(), line));
+        Ok(())
+    }
+
+    pub fn stats_mut(&self) -> Option<RefMut<'_, Stats>> {
+        self.stats.as_ref().map(|stats| stats.borrow_mut())
+    }
+
     pub fn add_scope(&mut self) {
-        self.layers.push(Rc::new(RefCell::new(HashMap::new())));
+        self.layers.push(Rc::new(RefCell::new(BTreeMap::new())));
+        if let Some(mut stats) = self.stats_mut() {
+            // the globals are depth 0
+            stats.max_scope_depth = stats.max_scope_depth.max(self.layers.len() - 1);
+        }
     }
 
     pub fn del_scope(&mut self) {
@@ -68,7 +184,7 @@ impl Environment {
             .insert(name.to_string(), value);
     }
 
-    pub fn ancestor(&mut self, distance: usize) -> Rc<RefCell<HashMap<String, Literal>>> {
+    pub fn ancestor(&mut self, distance: usize) -> Rc<RefCell<BTreeMap<String, Literal>>> {
         Rc::clone(self.layers.get(self.layers.len() - distance - 1).unwrap())
     }
 
@@ -87,38 +203,69 @@ impl Environment {
     ) -> Result<Literal, (Token, Soo)> {
         self.ancestor(distance)
             .borrow_mut()
-            .insert(name.lexeme.to_owned(), value.to_owned());
+            .insert(name.lexeme.to_string(), value.to_owned());
         Ok(value)
     }
 
     pub fn assign(&mut self, name: &Token, value: Literal) -> Result<Literal, (Token, Soo)> {
         for values in self.layers.iter_mut().rev() {
-            if values.borrow().contains_key(&name.lexeme) {
+            if values.borrow().contains_key(&*name.lexeme) {
                 values
                     .borrow_mut()
-                    .insert(name.lexeme.to_owned(), value.to_owned());
+                    .insert(name.lexeme.to_string(), value.to_owned());
                 return Ok(value);
             }
         }
 
         Err((
             name.clone(),
-            Soo::Owned(format!("Undefined variable '{}'.", name.lexeme)),
+            localize(Message::UndefinedVariable, &[&name.lexeme]),
         ))
     }
 
     pub fn assign_global(&mut self, name: &Token, value: Literal) -> Result<Literal, (Token, Soo)> {
         let global = self.layers.get(0).unwrap();
-        if global.borrow().contains_key(&name.lexeme) {
+        if global.borrow().contains_key(&*name.lexeme) {
             global
                 .borrow_mut()
-                .insert(name.lexeme.to_owned(), value.to_owned());
+                .insert(name.lexeme.to_string(), value.to_owned());
             Ok(value)
         } else {
             Err((
                 name.clone(),
-                Soo::Owned(format!("Undefined variable '{}'.", name.lexeme)),
+                localize(Message::UndefinedVariable, &[&name.lexeme]),
             ))
         }
     }
 }
+
+/// Adds a scope to an environment and removes it again when dropped, so the
+/// scope is popped even when an error returns early.
+pub struct ScopeGuard<'a>(&'a mut Environment);
+
+impl<'a> ScopeGuard<'a> {
+    pub fn new(environment: &'a mut Environment) -> Self {
+        environment.add_scope();
+        ScopeGuard(environment)
+    }
+}
+
+impl Deref for ScopeGuard<'_> {
+    type Target = Environment;
+
+    fn deref(&self) -> &Environment {
+        self.0
+    }
+}
+
+impl DerefMut for ScopeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Environment {
+        self.0
+    }
+}
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.del_scope();
+    }
+}
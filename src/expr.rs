@@ -1,4 +1,7 @@
-use crate::token::{Literal, Token};
+use crate::{
+    stmt::Stmt,
+    token::{Literal, Token},
+};
 
 #[derive(Clone, Debug)]
 pub struct Expr(pub usize, pub ExprKind);
@@ -13,6 +16,13 @@ pub enum ExprKind {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
+        /// the lines the left and right operands start on, for runtime errors
+        operand_lines: (usize, usize),
+    },
+    /// `{ statements value }`, runs the statements in a new scope and yields the value
+    BlockExpr {
+        statements: Vec<Stmt>,
+        value: Box<Expr>,
     },
     Get {
         object: Box<Expr>,
@@ -34,6 +44,11 @@ pub enum ExprKind {
         operator: Token,
         right: Box<Expr>,
     },
+    /// `object?.name`, which is nil instead of an error when the object is nil
+    OptionalGet {
+        object: Box<Expr>,
+        name: Token,
+    },
     Set {
         object: Box<Expr>,
         name: Token,
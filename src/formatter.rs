@@ -0,0 +1,468 @@
+use crate::{
+    expr::{Expr, ExprKind},
+    stmt::{Function, Stmt},
+    token::{Literal, Token},
+    token_type::TokenType::*,
+};
+
+/// indentation used when none is configured
+pub const DEFAULT_INDENT: &str = "  ";
+
+/// Reprint a program with consistent indentation and spacing, from the tokens and
+/// comments of `Scanner::scan_tokens_and_comments`.
+///
+/// Only whitespace changes: tokens keep their spelling and order, so `for` loops and
+/// string escapes stay as written, comments stay on their lines, and a run of blank
+/// lines becomes one.
+pub fn format(tokens: &[Token], comments: &[(usize, String)], indent: &str) -> String {
+    let mut formatter = Formatter {
+        indent,
+        output: String::new(),
+        braces: Vec::new(),
+        depth: 0,
+        parens: 0,
+        wrapped: false,
+        class_header: false,
+        last_line: 0,
+        previous: None,
+        ends_operand: false,
+        unary: false,
+    };
+
+    let mut comments = comments.iter().peekable();
+    for (i, token) in tokens.iter().enumerate() {
+        // a comment runs to the end of its line, so it comes before any token on a later one
+        while let Some((line, text)) = comments.peek()
+            && *line < token.line
+        {
+            formatter.comment(*line, text);
+            comments.next();
+        }
+        formatter.token(token, tokens.get(i + 1));
+    }
+    for (line, text) in comments {
+        formatter.comment(*line, text);
+    }
+
+    formatter.newline();
+    formatter.output
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Brace {
+    /// the braces of a block statement, class or function body, which indent their contents
+    Block,
+    /// the braces of a block expression, which is kept on one line
+    Inline,
+}
+
+struct Formatter<'a> {
+    indent: &'a str,
+    output: String,
+    /// each brace that is open, innermost last
+    braces: Vec<Brace>,
+    /// how many blocks are open, which is how far lines are indented
+    depth: usize,
+    /// how many parentheses are open, in which a `;` separates `for` clauses
+    parens: usize,
+    /// whether a comment has split the current statement, indenting the rest of it
+    wrapped: bool,
+    /// whether a `class` is waiting for the `{` of its body
+    class_header: bool,
+    /// line the previous token or comment ended on
+    last_line: usize,
+    previous: Option<&'a Token>,
+    /// whether the previous token ends an operand, so a `-` after it is binary
+    ends_operand: bool,
+    /// whether the previous token is a unary operator
+    unary: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn at_line_start(&self) -> bool {
+        self.output.is_empty() || self.output.ends_with('\n')
+    }
+
+    fn newline(&mut self) {
+        if !self.at_line_start() {
+            self.output.push('\n');
+        }
+    }
+
+    /// indent a new line for something starting on `line`, after a blank line if the
+    /// source had one there
+    fn start_line(&mut self, line: usize, closes_block: bool) {
+        if line > self.last_line + 1
+            && !self.output.is_empty()
+            && !self.output.ends_with("{\n")
+            && !closes_block
+        {
+            self.output.push('\n');
+        }
+        push_indent(
+            self.depth + usize::from(self.wrapped),
+            self.indent,
+            &mut self.output,
+        );
+    }
+
+    fn comment(&mut self, line: usize, text: &str) {
+        if line == self.last_line && !self.output.is_empty() {
+            // the comment follows code on its line, so it stays on that line
+            if self.at_line_start() {
+                self.output.pop();
+            } else {
+                self.wrapped = true;
+            }
+            self.output.push(' ');
+        } else {
+            if !self.at_line_start() {
+                self.wrapped = true;
+                self.newline();
+            }
+            self.start_line(line, false);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+        self.last_line = line;
+    }
+
+    fn token(&mut self, token: &'a Token, next: Option<&Token>) {
+        let brace = match token.typ {
+            LeftBrace => Some(self.open_brace_kind()),
+            RightBrace => self.braces.pop(),
+            _ => None,
+        };
+        if token.typ == RightBrace && brace == Some(Brace::Block) {
+            self.depth = self.depth.saturating_sub(1);
+            self.wrapped = false;
+            if self.output.ends_with("{\n") {
+                // an empty block is written `{}`
+                self.output.pop();
+            } else {
+                self.newline();
+            }
+        }
+
+        let first_line = token.line - token.lexeme.matches('\n').count();
+        if self.at_line_start() {
+            self.start_line(first_line, brace == Some(Brace::Block));
+        } else if self.needs_space(token) {
+            self.output.push(' ');
+        }
+        self.output.push_str(&token.lexeme);
+        self.last_line = token.line;
+
+        match (token.typ, brace) {
+            (LeftBrace, Some(kind)) => {
+                self.braces.push(kind);
+                if kind == Brace::Block {
+                    self.depth += 1;
+                    self.wrapped = false;
+                    self.class_header = false;
+                    self.newline();
+                }
+            }
+            (RightBrace, Some(Brace::Block)) if next.map(|next| next.typ) != Some(Else) => {
+                self.newline();
+            }
+            // a statement ends its line, unless an `else` or empty statement follows it
+            (Semicolon, _)
+                if self.parens == 0
+                    && self.braces.last() != Some(&Brace::Inline)
+                    && !matches!(next.map(|next| next.typ), Some(Else | Semicolon)) =>
+            {
+                self.wrapped = false;
+                self.newline();
+            }
+            (LeftParen, _) => self.parens += 1,
+            (RightParen, _) => self.parens = self.parens.saturating_sub(1),
+            (Class, _) => self.class_header = true,
+            _ => {}
+        }
+
+        self.unary = token.typ == Bang || (token.typ == Minus && !self.ends_operand);
+        self.ends_operand = match token.typ {
+            Identifier | Number | StringToken | RightParen | True | False | Nil | This => true,
+            RightBrace => brace == Some(Brace::Inline),
+            _ => false,
+        };
+        self.previous = Some(token);
+    }
+
+    /// A `{` opens a block where a statement can start, or after a class name or the
+    /// header of a function or control flow statement. Anywhere else it opens a block
+    /// expression, as does every brace inside one.
+    fn open_brace_kind(&self) -> Brace {
+        if self.braces.last() == Some(&Brace::Inline) {
+            return Brace::Inline;
+        }
+        match self.previous {
+            None => Brace::Block,
+            _ if self.class_header => Brace::Block,
+            Some(previous) => match previous.typ {
+                Semicolon | RightParen | Else | LeftBrace | RightBrace => Brace::Block,
+                _ => Brace::Inline,
+            },
+        }
+    }
+
+    fn needs_space(&self, token: &Token) -> bool {
+        let previous = match self.previous {
+            Some(previous) => previous,
+            None => return false,
+        };
+        // `- -x` keeps its space so it doesn't read as `--`
+        let after_unary = self.unary && !(previous.typ == Minus && token.typ == Minus);
+        !(after_unary
+            || matches!(
+                token.typ,
+                RightParen | Comma | Semicolon | Dot | QuestionDot | Colon
+            )
+            || matches!(previous.typ, LeftParen | Dot | QuestionDot)
+            || (previous.typ == LeftBrace && token.typ == RightBrace)
+            || (token.typ == LeftParen && matches!(previous.typ, Identifier | RightParen)))
+    }
+}
+
+fn push_indent(depth: usize, indent: &str, output: &mut String) {
+    for _ in 0..depth {
+        output.push_str(indent);
+    }
+}
+
+fn format_stmt(stmt: &Stmt, depth: usize, indent: &str, output: &mut String) {
+    push_indent(depth, indent, output);
+    format_stmt_inline(stmt, depth, indent, output);
+}
+
+/// format a statement that has already been indented
+fn format_stmt_inline(stmt: &Stmt, depth: usize, indent: &str, output: &mut String) {
+    match stmt {
+        Stmt::Assert {
+            keyword: _,
+            condition,
+            source: _,
+        } => output.push_str(&format!("assert {};", format_expr(condition))),
+        Stmt::Block { statements } => format_block(statements, depth, indent, output),
+        Stmt::Class {
+            name,
+            superclass,
+            fields,
+            methods,
+        } => {
+            output.push_str(&format!("class {name}"));
+            if let Some(expr) = superclass {
+                output.push_str(&format!(" < {}", format_expr(expr)));
+            }
+            if fields.is_empty() && methods.is_empty() {
+                output.push_str(" {}");
+                return;
+            }
+
+            output.push_str(" {\n");
+            for field in fields {
+                push_indent(depth + 1, indent, output);
+                match &field.initializer {
+                    Some(expr) => {
+                        output.push_str(&format!("var {} = {};\n", field.name, format_expr(expr)))
+                    }
+                    _ => output.push_str(&format!("var {};\n", field.name)),
+                }
+            }
+            for (i, method) in methods.iter().enumerate() {
+                if i > 0 || !fields.is_empty() {
+                    output.push('\n');
+                }
+                push_indent(depth + 1, indent, output);
+                format_function(method, depth + 1, indent, output);
+                output.push('\n');
+            }
+            push_indent(depth, indent, output);
+            output.push('}');
+        }
+        Stmt::Expression { expression } => {
+            output.push_str(&format!("{};", format_expr(expression)));
+        }
+        Stmt::Function(function) => {
+            output.push_str("fun ");
+            format_function(function, depth, indent, output);
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_ifs,
+            else_branch,
+        } => {
+            output.push_str(&format!("if ({}) ", format_expr(condition)));
+            format_stmt_inline(then_branch, depth, indent, output);
+            for (condition, stmt) in else_ifs {
+                output.push_str(&format!(" else if ({}) ", format_expr(condition)));
+                format_stmt_inline(stmt, depth, indent, output);
+            }
+            if let Some(stmt) = else_branch {
+                output.push_str(" else ");
+                format_stmt_inline(stmt, depth, indent, output);
+            }
+        }
+        Stmt::Import { path, alias } => {
+            output.push_str(&format!("import {}", format_literal(&path.literal)));
+            if let Some(alias) = alias {
+                output.push_str(&format!(" as {alias}"));
+            }
+            output.push(';');
+        }
+        Stmt::Print { expression } => {
+            output.push_str(&format!("print {};", format_expr(expression)));
+        }
+        Stmt::Return { keyword: _, value } => match value {
+            Some(expr) => output.push_str(&format!("return {};", format_expr(expr))),
+            _ => output.push_str("return;"),
+        },
+        Stmt::Var { name, initializer } => match initializer {
+            Some(expr) => output.push_str(&format!("var {name} = {};", format_expr(expr))),
+            _ => output.push_str(&format!("var {name};")),
+        },
+        Stmt::While { condition, body } => {
+            output.push_str(&format!("while ({}) ", format_expr(condition)));
+            format_stmt_inline(body, depth, indent, output);
+        }
+    }
+}
+
+fn format_block(statements: &[Stmt], depth: usize, indent: &str, output: &mut String) {
+    if statements.is_empty() {
+        output.push_str("{}");
+        return;
+    }
+
+    output.push_str("{\n");
+    for stmt in statements {
+        format_stmt(stmt, depth + 1, indent, output);
+        output.push('\n');
+    }
+    push_indent(depth, indent, output);
+    output.push('}');
+}
+
+fn format_function(function: &Function, depth: usize, indent: &str, output: &mut String) {
+    let params: Vec<String> = function
+        .params
+        .iter()
+        .zip(&function.defaults)
+        .map(|(param, default)| match default {
+            Some(expr) => format!("{param} = {}", format_expr(expr)),
+            _ => param.lexeme.to_string(),
+        })
+        .collect();
+    output.push_str(&format!("{}({}) ", function.name, params.join(", ")));
+    format_block(&function.body, depth, indent, output);
+}
+
+/// print an expression from the AST as Lox source, such as a failed assertion's condition
+pub fn format_expr(expr: &Expr) -> String {
+    match &expr.1 {
+        ExprKind::Assign { name, value } => format!("{name} = {}", format_expr(value)),
+        ExprKind::Binary {
+            left,
+            operator,
+            right,
+            operand_lines: _,
+        }
+        | ExprKind::Logical {
+            left,
+            operator,
+            right,
+        } => format!("{} {operator} {}", format_expr(left), format_expr(right)),
+        // block expressions are kept on one line, so statements in them aren't indented
+        ExprKind::BlockExpr { statements, value } => {
+            let mut output = String::from("{ ");
+            for stmt in statements {
+                format_stmt_inline(stmt, 0, "", &mut output);
+                output.push(' ');
+            }
+            output.push_str(&format!("{} }}", format_expr(value)));
+            output
+        }
+        ExprKind::Call {
+            callee,
+            paren: _,
+            arguments,
+        } => {
+            let arguments: Vec<String> = arguments.iter().map(format_expr).collect();
+            format!("{}({})", format_expr(callee), arguments.join(", "))
+        }
+        ExprKind::Get { object, name } => format!("{}.{name}", format_expr(object)),
+        ExprKind::OptionalGet { object, name } => format!("{}?.{name}", format_expr(object)),
+        ExprKind::Grouping { expression } => format!("({})", format_expr(expression)),
+        ExprKind::LiteralExpr { value } => format_literal(value),
+        ExprKind::Set {
+            object,
+            name,
+            value,
+        } => format!("{}.{name} = {}", format_expr(object), format_expr(value)),
+        ExprKind::Super { keyword: _, method } => format!("super.{method}"),
+        ExprKind::This { keyword: _ } => "this".to_owned(),
+        ExprKind::Unary { operator, right } => format!("{operator}{}", format_expr(right)),
+        ExprKind::Variable { name } => name.lexeme.to_string(),
+    }
+}
+
+fn format_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::StringLiteral(s) => {
+            // a quote, or a backslash that would start an escape, is written as an escape
+            let mut quoted = String::from('"');
+            let mut chars = s.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => quoted.push_str("\\x22"),
+                    '\\' if chars.peek() == Some(&'x') => quoted.push_str("\\x5c"),
+                    _ => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        Literal::None => "nil".to_owned(),
+        _ => literal.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    fn format_source(source: &str) -> String {
+        let (tokens, had_error, comments) = Scanner::new(source).scan_tokens_and_comments();
+        assert!(!had_error);
+        format(&tokens, &comments, DEFAULT_INDENT)
+    }
+
+    #[test]
+    fn formatting_keeps_the_shape_of_the_source() {
+        let source = "// a counter\nclass A<B{var n=1;init(x){this.x=x;}}\n\n\n\
+                      fun f(a,b=2){for(var i=0;i<a;i=i+1)print -i; // each\n\
+                      return a?.b;}\n\
+                      if(!x and y){print \"say \\x22hi\\x22\";}else if(x)print 2;else{}\n\
+                      print{var t=1;t-1};;print - -1;\n";
+        let expected = "// a counter\n\
+                        class A < B {\n  var n = 1;\n  init(x) {\n    this.x = x;\n  }\n}\n\n\
+                        fun f(a, b = 2) {\n  for (var i = 0; i < a; i = i + 1) print -i; // each\n  \
+                        return a?.b;\n}\n\
+                        if (!x and y) {\n  print \"say \\x22hi\\x22\";\n} else if (x) print 2; else {}\n\
+                        print { var t = 1; t - 1 };;\nprint - -1;\n";
+        assert_eq!(format_source(source), expected);
+        assert_eq!(format_source(expected), expected);
+    }
+
+    #[test]
+    fn comments_inside_a_statement_indent_the_rest_of_it() {
+        let source = "{\nvar total = 1 + // first\n2;\n\n// next\nprint total;\n}\n";
+        assert_eq!(
+            format_source(source),
+            "{\n  var total = 1 + // first\n    2;\n\n  // next\n  print total;\n}\n"
+        );
+    }
+}
@@ -1,27 +1,28 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
 use crate::{
     class::Class,
     token::{Literal, Token},
-    utils::Soo,
+    utils::{localize, Message, Soo},
 };
 
 #[derive(Debug)]
 pub struct Instance {
     pub class: Class,
-    fields: Rc<RefCell<HashMap<String, Literal>>>,
+    /// sorted by name, so fields are always listed in the same order
+    fields: Rc<RefCell<BTreeMap<String, Literal>>>,
 }
 
 impl Instance {
     pub fn new(class: Class) -> Self {
         Instance {
             class,
-            fields: Rc::new(RefCell::new(HashMap::new())),
+            fields: Rc::new(RefCell::new(BTreeMap::new())),
         }
     }
 
     pub fn get(&mut self, name: &Token) -> Result<Literal, (Token, Soo)> {
-        match self.fields.borrow_mut().get(&name.lexeme) {
+        match self.fields.borrow_mut().get(&*name.lexeme) {
             Some(value) => Ok(value.clone()),
             _ => match self.class.find_method(&name.lexeme) {
                 Some(mut method) => {
@@ -30,16 +31,45 @@ impl Instance {
                 }
                 _ => Err((
                     name.clone(),
-                    format!("Undefined property '{}'.", name.lexeme).into(),
+                    localize(Message::UndefinedProperty, &[&name.lexeme]),
                 )),
             },
         }
     }
 
-    pub fn set(&mut self, name: &Token, value: Literal) {
+    /// a copy of the fields, sorted by name
+    pub fn fields(&self) -> Vec<(String, Literal)> {
         self.fields
-            .borrow_mut()
-            .insert(name.lexeme.to_owned(), value);
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.to_owned(), value.clone()))
+            .collect()
+    }
+
+    /// identifies the instance: handles from `Clone::clone` share the fields and so the
+    /// id, while a copy from the `clone` native, `shallow_copy`, gets its own
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.fields) as usize
+    }
+
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.borrow().contains_key(name)
+    }
+
+    pub fn set(&mut self, name: &Token, value: Literal) {
+        self.set_field(&name.lexeme, value);
+    }
+
+    pub fn set_field(&mut self, name: &str, value: Literal) {
+        self.fields.borrow_mut().insert(name.to_owned(), value);
+    }
+
+    /// create a new instance of the same class with a copy of this instance's fields
+    pub fn shallow_copy(&self) -> Self {
+        Instance {
+            class: self.class.clone(),
+            fields: Rc::new(RefCell::new(self.fields.borrow().clone())),
+        }
     }
 
     pub fn to_string(&self) -> String {
@@ -1,72 +1,442 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use crate::callable::{Callable, CallableKind};
-use crate::environment::Environment;
-use crate::runtime_error;
+use crate::class::{Class, Fields};
+use crate::environment::{Environment, ScopeGuard};
+use crate::instance::Instance;
+use crate::scanner::Scanner;
 use crate::stmt::Stmt;
 use crate::token::{Literal::*, Token};
 use crate::token_type::TokenType;
-use crate::utils::Soo;
+use crate::utils::{localize, Message, Soo};
 use crate::{expr::*, token::Literal};
+use crate::{parser, resolver, runtime_error};
+
+/// longest string, in bytes, that `*` repetition may build
+const MAX_REPEAT_LENGTH: usize = 1 << 28;
+/// how many of the innermost and outermost frames a backtrace shows
+const BACKTRACE_ENDS: usize = 10;
+
+/// Why running code stopped before finishing: a runtime error, or a `return` unwinding
+/// to the call it returns from.
+pub enum Unwind {
+    Error(Token, Soo),
+    /// the returned value and the line of the `return`
+    Return(Literal, usize),
+    /// a `return f(...)` the resolver marked as a tail call, which `Callable::call` runs
+    /// in place when `f` is the function returning
+    TailCall(Box<TailCall>),
+}
+
+pub struct TailCall {
+    pub callee: Callable,
+    pub arguments: Vec<Literal>,
+    pub paren: Token,
+}
+
+impl From<(Token, Soo)> for Unwind {
+    fn from((token, message): (Token, Soo)) -> Self {
+        Unwind::Error(token, message)
+    }
+}
+
+impl Unwind {
+    /// the error to report when this reaches code outside any function, where the
+    /// resolver already rejects `return`
+    pub fn into_error(self) -> (Token, Soo) {
+        let outside = "'return' outside of a function.";
+        match self {
+            Unwind::Error(token, message) => (token, message),
+            Unwind::Return(_, line) => (
+                Token {
+                    typ: TokenType::Return,
+                    lexeme: "return".into(),
+                    literal: None,
+                    line,
+                },
+                outside.into(),
+            ),
+            Unwind::TailCall(tail_call) => (tail_call.paren, outside.into()),
+        }
+    }
+}
 
 pub fn interpret(statements: Vec<Stmt>, environment: &mut Environment) -> bool {
     for mut statement in statements.into_iter() {
-        match &mut statement.interpret(environment) {
-            Err((token, message)) => {
-                runtime_error(token.line, message);
-                return true;
+        if let Err(unwind) = statement.interpret(environment) {
+            let (token, mut message) = unwind.into_error();
+            runtime_error(token.line, &mut message);
+            let frames: Vec<_> = environment
+                .call_stack
+                .borrow_mut()
+                .drain(..)
+                .rev()
+                .collect();
+            for (index, (name, line)) in frames.iter().enumerate() {
+                if frames.len() > 2 * BACKTRACE_ENDS && index == BACKTRACE_ENDS {
+                    println!("  … {} more", frames.len() - 2 * BACKTRACE_ENDS);
+                }
+                if index < BACKTRACE_ENDS || index + BACKTRACE_ENDS >= frames.len() {
+                    println!("  in {name}() called from [line {line}]");
+                }
             }
-            _ => {}
-        };
+            return true;
+        }
     }
     false
 }
 
-fn stringify(literal: Literal) -> String {
+/// run Lox source against the globals of the running program, producing the value of a
+/// final expression statement, or nil
+pub fn eval(
+    source: &str,
+    token: &Token,
+    environment: &mut Environment,
+) -> Result<Literal, (Token, Soo)> {
+    let mut globals = environment.globals_only();
+    let mut statements = compile_top_level(source, token, &mut globals, || {
+        "Unable to compile the source passed to 'eval'.".into()
+    })?;
+    run_top_level(&mut statements, "eval", token, &mut globals)
+}
+
+/// Evaluate source that must be a single expression against the globals of the running
+/// program. Anything else, such as a declaration, is reported and fails to compile.
+pub fn eval_expression(
+    source: &str,
+    token: &Token,
+    environment: &mut Environment,
+) -> Result<Literal, (Token, Soo)> {
+    // scan and parse errors have already been reported by the time they return
+    let compile_error = || {
+        (
+            token.clone(),
+            format!(
+                "Unable to compile the expression passed to '{}'.",
+                token.lexeme
+            )
+            .into(),
+        )
+    };
+
+    let (tokens, had_error) = Scanner::new(source).scan_tokens();
+    if had_error {
+        return Err(compile_error());
+    }
+    let expression = parser::parse_expression(tokens).map_err(|_| compile_error())?;
+
+    let mut globals = environment.globals_only();
+    let mut statements = [Stmt::Expression {
+        expression: Box::new(expression),
+    }];
+    resolve_top_level(&mut statements, token, &mut globals, compile_error)?;
+    run_top_level(&mut statements, &token.lexeme, token, &mut globals)
+}
+
+/// Run the file named by an `import`, resolving its path relative to the file doing
+/// the import.
+///
+/// Without an alias the file defines globals of the running program. With one, it
+/// gets its own globals, which are copied into the fields of an instance bound to the
+/// alias once the file has run. The fields are a snapshot: the file's functions keep
+/// using its globals, so `m.count` doesn't see a change `m.inc()` makes to `count`.
+fn import(
+    path: &Token,
+    alias: &Option<Token>,
+    environment: &mut Environment,
+) -> Result<(), (Token, Soo)> {
+    let base = match environment.imports.borrow().last() {
+        Some(importer) => importer.parent().map(Path::to_path_buf).unwrap_or_default(),
+        _ => PathBuf::new(),
+    };
+    let file = base.join(&*path.lexeme);
+    let unreadable = |error: io::Error| -> (Token, Soo) {
+        (
+            path.clone(),
+            format!("Unable to read '{}': {error}.", path.lexeme).into(),
+        )
+    };
+    let file = file.canonicalize().map_err(unreadable)?;
+    if environment.imports.borrow().contains(&file) {
+        return Err((
+            path.clone(),
+            format!("Cyclic import of '{}'.", path.lexeme).into(),
+        ));
+    }
+    let source = fs::read_to_string(&file).map_err(unreadable)?;
+
+    let mut globals = match alias {
+        Some(_) => environment.with_own_globals(),
+        _ => environment.globals_only(),
+    };
+
+    environment.imports.borrow_mut().push(file);
+    let compiled = compile_top_level(&source, path, &mut globals, || {
+        format!("Unable to compile '{}'.", path.lexeme).into()
+    });
+    let result = match compiled {
+        Ok(mut statements) => {
+            run_top_level(&mut statements, "import", path, &mut globals).map(|_| statements)
+        }
+        error => error,
+    };
+    environment.imports.borrow_mut().pop();
+    let statements = result?;
+
+    if let Some(alias) = alias {
+        let mut namespace = Instance::new(Class::new(
+            alias.lexeme.to_string(),
+            Option::None,
+            HashMap::new(),
+            Option::None,
+        ));
+        // a predefined name like `PI` only belongs to the file if it declared it
+        let declared = declared_globals(&statements);
+        for (name, value) in globals.layers[0].borrow().iter() {
+            if !Environment::is_predefined(name) || declared.contains(name.as_str()) {
+                namespace.set_field(name, value.clone());
+            }
+        }
+        environment.define(&alias.lexeme, InstanceLiteral(namespace));
+    }
+
+    Ok(())
+}
+
+/// Scan, parse and resolve source as top-level code, so it only sees and defines globals.
+fn compile_top_level(
+    source: &str,
+    token: &Token,
+    globals: &mut Environment,
+    compile_message: impl Fn() -> Soo,
+) -> Result<Vec<Stmt>, (Token, Soo)> {
+    // scan and parse errors have already been reported by the time they return
+    let compile_error = || (token.clone(), compile_message());
+
+    let (tokens, had_error) = Scanner::new(source).scan_tokens();
+    if had_error {
+        return Err(compile_error());
+    }
+    let mut statements = parser::parse(tokens, Option::None).map_err(|_| compile_error())?;
+    resolve_top_level(&mut statements, token, globals, compile_error)?;
+    Ok(statements)
+}
+
+/// Resolve parsed top-level statements, returning `compile_error()` if any fail to resolve.
+fn resolve_top_level(
+    statements: &mut [Stmt],
+    token: &Token,
+    globals: &mut Environment,
+    compile_error: impl Fn() -> (Token, Soo),
+) -> Result<(), (Token, Soo)> {
+    let mut had_error = false;
+    resolver::resolve_statements(
+        statements,
+        globals,
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut had_error,
+    )
+    .map_err(|(_, message)| (token.clone(), message))?;
+    if had_error {
+        return Err(compile_error());
+    }
+    Ok(())
+}
+
+/// Run statements from `compile_top_level`, producing the value of a final expression
+/// statement, or nil.
+///
+/// Calls made by the statements are reported as called from `frame` at the token's line.
+fn run_top_level(
+    statements: &mut [Stmt],
+    frame: &str,
+    token: &Token,
+    globals: &mut Environment,
+) -> Result<Literal, (Token, Soo)> {
+    globals
+        .push_frame(frame, token.line)
+        .map_err(|message| (token.clone(), message))?;
+    let mut value = None;
+    let last = statements.len().saturating_sub(1);
+    for (i, stmt) in statements.iter_mut().enumerate() {
+        match stmt {
+            Stmt::Expression { expression } if i == last => {
+                value = expression.interpret(globals).map_err(Unwind::into_error)?
+            }
+            _ => {
+                stmt.interpret(globals).map_err(Unwind::into_error)?;
+            }
+        }
+    }
+    globals.call_stack.borrow_mut().pop();
+
+    Ok(value)
+}
+
+/// the names a program's top level declares, each of which it defines as a global
+fn declared_globals(statements: &[Stmt]) -> HashSet<&str> {
+    statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Class { name, .. } | Stmt::Var { name, .. } => Some(&*name.lexeme),
+            Stmt::Function(function) => Some(&*function.name.lexeme),
+            Stmt::Import {
+                path: _,
+                alias: Some(alias),
+            } => Some(&*alias.lexeme),
+            _ => Option::None,
+        })
+        .collect()
+}
+
+/// the printed form of a value, instances of a class with a `toString` method use its result
+pub fn stringify(literal: Literal, environment: &mut Environment) -> Result<String, (Token, Soo)> {
+    match literal {
+        InstanceLiteral(instance) => match instance.class.find_method("toString") {
+            Some(mut method) => {
+                let token = match &method.kind {
+                    CallableKind::Function {
+                        declaration,
+                        closure: _,
+                        is_initializer: _,
+                    } => declaration.name.clone(),
+                    _ => unreachable!("methods are always functions"),
+                };
+                method
+                    .check_arity(0)
+                    .map_err(|message| (token.clone(), message))?;
+                method.bind(instance);
+                match method.call(Vec::new(), &token, environment)? {
+                    StringLiteral(s) => Ok(s.to_string()),
+                    _ => Err((token, "'toString' must return a string.".into())),
+                }
+            }
+            _ => Ok(instance.to_string()),
+        },
+        literal => Ok(stringify_value(literal, environment.precision)),
+    }
+}
+
+fn stringify_value(literal: Literal, precision: Option<usize>) -> String {
     match literal {
         BoolLiteral(b) => b.to_string(),
-        CallableLiteral(function) => match function.kind {
+        CallableLiteral(function) => match &function.kind {
             CallableKind::Class(class) => class.to_string(),
             CallableKind::Function {
                 declaration,
                 closure: _,
                 is_initializer: _,
-            } => format!("<fn {}>", declaration.name.lexeme),
-            CallableKind::Native(_) => "<native fn>".to_owned(),
+            } => format!(
+                "<fn {}({})>",
+                declaration.name.lexeme,
+                function.parameters.join(", ")
+            ),
+            CallableKind::Native(name) if function.variadic => {
+                format!("<native fn {name}({}+)>", function.arity)
+            }
+            CallableKind::Native(name) => format!("<native fn {name}({})>", function.arity),
         },
-        F64(f) => {
-            if f.fract() == 0f64 {
-                (f as i64).to_string()
+        F64(f) => format_number(f, precision),
+        IdentifierLiteral(ident) => ident.to_string(),
+        InstanceLiteral(instance) => instance.to_string(),
+        StringLiteral(s) => s.to_string(),
+        None => "nil".to_owned(),
+    }
+}
+
+/// debugging representation of a value, showing string quotes and instance fields
+pub fn repr(literal: &Literal, environment: &Environment) -> String {
+    repr_visiting(literal, environment, &mut HashSet::new())
+}
+
+/// instances already being printed further up are shown as `...` so cycles terminate
+fn repr_visiting(
+    literal: &Literal,
+    environment: &Environment,
+    visiting: &mut HashSet<usize>,
+) -> String {
+    match literal {
+        StringLiteral(s) => format!("{s:?}"),
+        InstanceLiteral(instance) => {
+            if !visiting.insert(instance.id()) {
+                return "...".to_owned();
+            }
+            let fields: Vec<String> = instance
+                .fields()
+                .iter()
+                .map(|(name, value)| {
+                    format!("{name}: {}", repr_visiting(value, environment, visiting))
+                })
+                .collect();
+            visiting.remove(&instance.id());
+
+            if fields.is_empty() {
+                instance.to_string()
             } else {
-                f.to_string()
+                format!("{} {{ {} }}", instance.to_string(), fields.join(", "))
             }
         }
-        IdentifierLiteral(ident) => ident,
-        InstanceLiteral(instance) => instance.to_string(),
-        StringLiteral(s) => s,
-        None => "nil".to_owned(),
+        _ => stringify_value(literal.clone(), environment.precision),
+    }
+}
+
+/// whole numbers print without a fractional part, which also prints `-0` as `0`,
+/// others are rounded to `precision` significant digits when one is given
+pub fn format_number(f: f64, precision: Option<usize>) -> String {
+    if f.fract() == 0f64 {
+        (f as i64).to_string()
+    } else if let Some(digits) = precision {
+        let rounded: f64 = format!("{:.*e}", digits - 1, f).parse().unwrap();
+        format_number(rounded, Option::None)
+    } else {
+        f.to_string()
     }
 }
 
 trait Interpreter {
-    fn interpret(&mut self, environment: &mut Environment) -> Result<Literal, (Token, Soo)>;
+    fn interpret(&mut self, environment: &mut Environment) -> Result<Literal, Unwind>;
 }
 
 impl Interpreter for Stmt {
-    fn interpret(&mut self, environment: &mut Environment) -> Result<Literal, (Token, Soo)> {
+    fn interpret(&mut self, environment: &mut Environment) -> Result<Literal, Unwind> {
+        if let Some(mut stats) = environment.stats_mut() {
+            stats.statements += 1;
+        }
+
         match self {
+            Stmt::Assert {
+                keyword,
+                condition,
+                source,
+            } => {
+                if !is_truthy(&condition.interpret(environment)?) {
+                    return Err(Unwind::Error(
+                        keyword.clone(),
+                        format!("Assertion failed: {source}.").into(),
+                    ));
+                }
+            }
             Stmt::Block { statements } => {
                 execute_block(statements, environment)?;
             }
             Stmt::Class {
                 name,
                 superclass: stmt_superclass,
+                fields: stmt_fields,
                 methods: stmt_methods,
             } => {
                 let superclass = match stmt_superclass {
                     Some(expr) => match expr.interpret(environment)? {
                         CallableLiteral(Callable {
                             arity: _,
+                            optional: _,
+                            variadic: _,
                             parameters: _,
                             kind: CallableKind::Class(class),
                         }) => Some(class),
@@ -75,7 +445,7 @@ impl Interpreter for Stmt {
                                 ExprKind::Variable { name } => name,
                                 _ => panic!("Superclass was not a variable."),
                             };
-                            return Err((
+                            return Err(Unwind::Error(
                                 superclass_name.clone(),
                                 "Superclass must be a class.".into(),
                             ));
@@ -94,6 +464,7 @@ impl Interpreter for Stmt {
                             value.name,
                             value.superclass.map(|c| *c),
                             value.methods,
+                            value.fields,
                         )),
                     );
                 }
@@ -103,16 +474,24 @@ impl Interpreter for Stmt {
                     let function = Callable::new_function(
                         method,
                         environment.clone(),
-                        method.name.lexeme == "init",
+                        &*method.name.lexeme == "init",
                     );
-                    methods.insert(method.name.lexeme.to_owned(), function);
+                    methods.insert(method.name.lexeme.to_string(), function);
                 }
 
+                let fields = (!stmt_fields.is_empty()).then(|| {
+                    Rc::new(Fields {
+                        declarations: stmt_fields.clone(),
+                        closure: environment.clone(),
+                    })
+                });
+
                 if superclass.is_some() {
                     environment.del_scope();
                 }
 
-                let class = Callable::new_class(name.lexeme.to_owned(), superclass, methods);
+                let class =
+                    Callable::new_class(name.lexeme.to_string(), superclass, methods, fields);
 
                 environment.assign(name, CallableLiteral(class))?;
             }
@@ -127,32 +506,55 @@ impl Interpreter for Stmt {
             Stmt::If {
                 condition,
                 then_branch,
+                else_ifs,
                 else_branch,
             } => {
                 if is_truthy(&condition.interpret(environment)?) {
                     then_branch.interpret(environment)?;
-                } else if let Some(else_stmt) = else_branch {
-                    else_stmt.interpret(environment)?;
+                    return Ok(Literal::None);
+                }
+                for (condition, stmt) in else_ifs {
+                    if is_truthy(&condition.interpret(environment)?) {
+                        stmt.interpret(environment)?;
+                        return Ok(Literal::None);
+                    }
+                }
+                if let Some(stmt) = else_branch {
+                    stmt.interpret(environment)?;
                 }
             }
+            Stmt::Import { path, alias } => import(path, alias, environment)?,
             Stmt::Print { expression } => {
                 let literal = expression.interpret(environment)?;
-                println!("{}", stringify(literal));
+                println!("{}", stringify(literal, environment)?);
             }
             Stmt::Return { keyword, value } => {
+                if let Some(expr) = value
+                    && environment.tail_calls.borrow().contains(&expr.0)
+                    && let ExprKind::Call {
+                        callee,
+                        paren,
+                        arguments,
+                    } = &mut expr.1
+                    && let CallableLiteral(callee) = callee.interpret(environment)?
+                {
+                    let mut func_args = Vec::new();
+                    for argument in arguments {
+                        func_args.push(argument.interpret(environment)?);
+                    }
+
+                    return Err(Unwind::TailCall(Box::new(TailCall {
+                        callee,
+                        arguments: func_args,
+                        paren: paren.clone(),
+                    })));
+                }
+
                 let value = match value {
                     Some(expr) => expr.interpret(environment)?,
                     _ => Literal::None,
                 };
-                return Err((
-                    Token {
-                        typ: TokenType::Return,
-                        lexeme: "RETURN".to_owned(),
-                        literal: value,
-                        line: keyword.line,
-                    },
-                    "".into(),
-                ));
+                return Err(Unwind::Return(value, keyword.line));
             }
             Stmt::Var { name, initializer } => {
                 let value = match initializer {
@@ -172,100 +574,164 @@ impl Interpreter for Stmt {
 }
 
 impl Interpreter for Expr {
-    fn interpret(&mut self, environment: &mut Environment) -> Result<Literal, (Token, Soo)> {
+    fn interpret(&mut self, environment: &mut Environment) -> Result<Literal, Unwind> {
         match &mut self.1 {
             ExprKind::Assign { name, value } => {
                 let literal = value.interpret(environment)?;
 
-                match environment.locals.get(&self.0) {
-                    Some(distance) => environment.assign_at(*distance, &name, literal),
+                match environment.resolved_depth(self.0) {
+                    Some(distance) => environment.assign_at(distance, &name, literal),
                     _ => environment.assign_global(&name, literal),
                 }
+                .map_err(Unwind::from)
             }
             ExprKind::Binary {
                 left: left_expr,
                 operator,
                 right: right_expr,
+                operand_lines,
             } => {
                 let left = left_expr.interpret(environment)?;
                 let right = right_expr.interpret(environment)?;
 
+                if let InstanceLiteral(instance) = &left
+                    && let Some(method_name) = operator_method(&operator.typ)
+                {
+                    let result = call_operator_method(
+                        instance.clone(),
+                        method_name,
+                        right.clone(),
+                        operator,
+                        environment,
+                    )?;
+                    match result {
+                        Some(result) if operator.typ == TokenType::BangEqual => {
+                            return Ok(BoolLiteral(!is_truthy(&result)));
+                        }
+                        Some(result) => return Ok(result),
+                        // instances without `equals` compare by identity
+                        _ if method_name == "equals" => {}
+                        _ => {
+                            return Err(Unwind::Error(
+                                operator.clone(),
+                                format!(
+                                    "Operator '{}' is not defined for '{}', define a '{method_name}' method.",
+                                    operator.lexeme,
+                                    instance.class.name
+                                )
+                                .into(),
+                            ));
+                        }
+                    }
+                }
+
                 match operator.typ {
                     TokenType::Plus => match (left, right) {
                         (F64(f1), F64(f2)) => Ok(F64(f1 + f2)),
-                        (StringLiteral(s1), StringLiteral(s2)) => Ok(StringLiteral(s1 + &s2)),
-                        _ => Err((
-                            operator.clone(),
-                            "Operands must be two numbers or two strings.".into(),
-                        )),
+                        (StringLiteral(s1), StringLiteral(s2)) => {
+                            Ok(StringLiteral(format!("{s1}{s2}").into()))
+                        }
+                        (StringLiteral(s), F64(f)) if environment.coerce_strings => {
+                            let f = format_number(f, environment.precision);
+                            Ok(StringLiteral(format!("{s}{f}").into()))
+                        }
+                        (F64(f), StringLiteral(s)) if environment.coerce_strings => {
+                            let f = format_number(f, environment.precision);
+                            Ok(StringLiteral(format!("{f}{s}").into()))
+                        }
+                        (left, right) => {
+                            // blame the right operand when the left one started a valid pairing
+                            let (side, line, value) = match (&left, &right) {
+                                (F64(_), _) | (StringLiteral(_), _) => {
+                                    ("right", operand_lines.1, right)
+                                }
+                                _ => ("left", operand_lines.0, left),
+                            };
+                            Err(Unwind::Error(
+                                operator.clone(),
+                                format!(
+                                    "Operands must be two numbers or two strings, but the {side} operand on line {line} is {}.",
+                                    describe_type(&value)
+                                )
+                                .into(),
+                            ))
+                        }
                     },
                     TokenType::Minus => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
+                        let (left, right) =
+                            get_numeric_operands(operator, left, right, *operand_lines)?;
                         Ok(F64(left - right))
                     }
                     TokenType::Slash => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
+                        let (left, right) =
+                            get_numeric_operands(operator, left, right, *operand_lines)?;
                         Ok(F64(left / right))
                     }
-                    TokenType::Star => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
-                        Ok(F64(left * right))
-                    }
+                    TokenType::Star => match (left, right) {
+                        (StringLiteral(s), F64(n)) | (F64(n), StringLiteral(s)) => {
+                            if n.fract() != 0.0 || n < 0.0 {
+                                return Err(Unwind::Error(
+                                    operator.clone(),
+                                    "String repetition count must be a non-negative integer."
+                                        .into(),
+                                ));
+                            }
+                            // `as` saturates, so a huge count still fails the length check
+                            match s.len().checked_mul(n as usize) {
+                                Some(length) if length <= MAX_REPEAT_LENGTH => {
+                                    Ok(StringLiteral(s.repeat(n as usize).into()))
+                                }
+                                _ => Err(Unwind::Error(
+                                    operator.clone(),
+                                    "String repetition result is too long.".into(),
+                                )),
+                            }
+                        }
+                        (left, right) => {
+                            let (left, right) =
+                                get_numeric_operands(operator, left, right, *operand_lines)?;
+                            Ok(F64(left * right))
+                        }
+                    },
                     TokenType::Greater => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
+                        let (left, right) =
+                            get_numeric_operands(operator, left, right, *operand_lines)?;
                         Ok(BoolLiteral(left > right))
                     }
                     TokenType::GreaterEqual => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
+                        let (left, right) =
+                            get_numeric_operands(operator, left, right, *operand_lines)?;
                         Ok(BoolLiteral(left >= right))
                     }
                     TokenType::Less => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
+                        let (left, right) =
+                            get_numeric_operands(operator, left, right, *operand_lines)?;
                         Ok(BoolLiteral(left < right))
                     }
                     TokenType::LessEqual => {
-                        let (left, right) = get_numeric_operands(operator, left, right)?;
+                        let (left, right) =
+                            get_numeric_operands(operator, left, right, *operand_lines)?;
                         Ok(BoolLiteral(left <= right))
                     }
                     TokenType::BangEqual => Ok(BoolLiteral(!is_equal(left, right))),
                     TokenType::EqualEqual => Ok(BoolLiteral(is_equal(left, right))),
-                    _ => Err((operator.clone(), "Expected a binary operator.".into())),
+                    _ => Err(Unwind::Error(
+                        operator.clone(),
+                        "Expected a binary operator.".into(),
+                    )),
                 }
             }
-            ExprKind::Call {
-                callee,
-                paren,
-                arguments,
-            } => {
-                let callee = callee.interpret(environment)?;
-
-                let mut func_args = Vec::new();
-                for argument in arguments {
-                    func_args.push(argument.interpret(environment)?);
-                }
-
-                match callee {
-                    CallableLiteral(function) => {
-                        if func_args.len() != function.arity {
-                            Err((
-                                paren.clone(),
-                                Soo::Owned(format!(
-                                    "Expected {} arguments but got {}.",
-                                    function.arity,
-                                    func_args.len()
-                                )),
-                            ))
-                        } else {
-                            function.call(func_args, paren)
-                        }
-                    }
-                    _ => Err((paren.clone(), "Can only call functions and classes.".into())),
+            ExprKind::BlockExpr { statements, value } => {
+                let mut environment = ScopeGuard::new(environment);
+                for stmt in statements {
+                    stmt.interpret(&mut environment)?;
                 }
+                value.interpret(&mut environment)
+            }
+            // a `?.` on nil makes the whole chain of gets and calls it's in nil
+            ExprKind::Call { .. } | ExprKind::Get { .. } | ExprKind::OptionalGet { .. } => {
+                Ok(evaluate_chain(self, environment)?.unwrap_or(Literal::None))
             }
-            ExprKind::Get { object, name } => match object.interpret(environment)? {
-                InstanceLiteral(mut instance) => instance.get(name),
-                _ => Err((name.clone(), "Only instances have properties.".into())),
-            },
             ExprKind::Grouping { expression } => expression.interpret(environment),
             ExprKind::LiteralExpr { value } => Ok(value.clone()),
             ExprKind::Logical {
@@ -300,13 +766,18 @@ impl Interpreter for Expr {
                     instance.set(name, value.to_owned());
                     Ok(value)
                 }
-                _ => Err((name.clone(), "Only instances have fields.".into())),
+                _ => Err(Unwind::Error(
+                    name.clone(),
+                    "Only instances have fields.".into(),
+                )),
             },
             ExprKind::Super { keyword: _, method } => {
-                let distance = *environment.locals.get(&self.0).unwrap();
-                let mut superclass = match environment.get_at(distance, "super").unwrap() {
+                let distance = environment.resolved_depth(self.0).unwrap();
+                let superclass = match environment.get_at(distance, "super").unwrap() {
                     CallableLiteral(Callable {
                         arity: _,
+                        optional: _,
+                        variadic: _,
                         parameters: _,
                         kind,
                     }) => match kind {
@@ -321,52 +792,78 @@ impl Interpreter for Expr {
                     _ => panic!("Subclass did not resolve to an instance."),
                 };
 
+                // `super` only looks up methods, fields live on the instance itself
                 match superclass.find_method(&method.lexeme) {
                     Some(mut method) => {
                         method.bind(object);
                         Ok(CallableLiteral(method))
                     }
-                    _ => Err((
+                    _ if object.has_field(&method.lexeme) => Err(Unwind::Error(
+                        method.clone(),
+                        format!(
+                            "'{0}' is a field, not a method of superclass '{1}', use 'this.{0}' instead.",
+                            method.lexeme, superclass.name
+                        )
+                        .into(),
+                    )),
+                    _ => Err(Unwind::Error(
                         method.clone(),
-                        format!("Undefined property '{}'.", method.lexeme).into(),
+                        format!(
+                            "Undefined method '{}' in superclass '{}'.",
+                            method.lexeme, superclass.name
+                        )
+                        .into(),
                     )),
                 }
             }
-            ExprKind::This { keyword } => lookup_variable(keyword, self.0, environment),
+            ExprKind::This { keyword } => {
+                lookup_variable(keyword, self.0, environment).map_err(Unwind::from)
+            }
             ExprKind::Unary { operator, right } => {
                 let right = right.interpret(environment)?;
                 match operator.typ {
                     TokenType::Bang => Ok(Literal::BoolLiteral(!is_truthy(&right))),
                     TokenType::Minus => match right {
                         F64(value) => Ok(F64(-value)),
-                        _ => Err((operator.clone(), "Operand must be a number.".into())),
+                        _ => Err(Unwind::Error(
+                            operator.clone(),
+                            "Operand must be a number.".into(),
+                        )),
                     },
-                    _ => Err((operator.clone(), "Expected a unary operator.".into())),
+                    _ => Err(Unwind::Error(
+                        operator.clone(),
+                        "Expected a unary operator.".into(),
+                    )),
                 }
             }
-            ExprKind::Variable { name } => lookup_variable(&name, self.0, environment),
+            ExprKind::Variable { name } => {
+                lookup_variable(&name, self.0, environment).map_err(Unwind::from)
+            }
         }
     }
 }
 
+pub fn evaluate(expr: &mut Expr, environment: &mut Environment) -> Result<Literal, Unwind> {
+    expr.interpret(environment)
+}
+
 pub fn execute_block(
     statements: &mut Vec<Stmt>,
     environment: &mut Environment,
-) -> Result<(), (Token, Soo)> {
-    environment.add_scope();
+) -> Result<(), Unwind> {
+    let mut environment = ScopeGuard::new(environment);
 
     for stmt in statements {
-        stmt.interpret(environment)?;
+        stmt.interpret(&mut environment)?;
     }
 
-    environment.del_scope();
     Ok(())
 }
 
 pub fn execute_statements(
     statements: &mut Vec<Stmt>,
     environment: &mut Environment,
-) -> Result<(), (Token, Soo)> {
+) -> Result<(), Unwind> {
     for stmt in statements {
         stmt.interpret(environment)?;
     }
@@ -377,22 +874,104 @@ fn get_numeric_operands(
     operator: &mut Token,
     left: Literal,
     right: Literal,
+    operand_lines: (usize, usize),
 ) -> Result<(f64, f64), (Token, Soo)> {
-    let message = "Operands must be numbers.";
+    let message = |side: &str, line: usize, value: &Literal| {
+        format!(
+            "Operands must be numbers, but the {side} operand on line {line} is {}.",
+            describe_type(value)
+        )
+    };
 
     let left = match left {
         F64(value) => value,
-        _ => return Err((operator.clone(), message.into())),
+        _ => {
+            return Err((
+                operator.clone(),
+                message("left", operand_lines.0, &left).into(),
+            ))
+        }
     };
     let right = match right {
         F64(value) => value,
-        _ => return Err((operator.clone(), message.into())),
+        _ => {
+            return Err((
+                operator.clone(),
+                message("right", operand_lines.1, &right).into(),
+            ))
+        }
     };
 
     Ok((left, right))
 }
 
-fn is_truthy(literal: &Literal) -> bool {
+/// the method a class defines to overload a binary operator
+fn operator_method(typ: &TokenType) -> Option<&'static str> {
+    match typ {
+        TokenType::Plus => Some("add"),
+        TokenType::Minus => Some("subtract"),
+        TokenType::Star => Some("multiply"),
+        TokenType::Slash => Some("divide"),
+        TokenType::Greater => Some("greater"),
+        TokenType::GreaterEqual => Some("greaterEqual"),
+        TokenType::Less => Some("less"),
+        TokenType::LessEqual => Some("lessEqual"),
+        TokenType::EqualEqual | TokenType::BangEqual => Some("equals"),
+        _ => Option::None,
+    }
+}
+
+/// call the left operand's operator method with the right operand, if its class defines one
+fn call_operator_method(
+    instance: Instance,
+    method_name: &str,
+    right: Literal,
+    operator: &Token,
+    environment: &mut Environment,
+) -> Result<Option<Literal>, (Token, Soo)> {
+    match instance.class.find_method(method_name) {
+        Some(mut method) => {
+            method
+                .check_arity(1)
+                .map_err(|message| (operator.clone(), message))?;
+            method.bind(instance);
+            method.call(vec![right], operator, environment).map(Some)
+        }
+        _ => Ok(Option::None),
+    }
+}
+
+/// the name of a value's type, as shown by the REPL's `:type`
+pub fn type_name(literal: &Literal) -> &'static str {
+    match literal {
+        BoolLiteral(_) => "boolean",
+        CallableLiteral(Callable {
+            kind: CallableKind::Class(_),
+            ..
+        }) => "class",
+        CallableLiteral(_) => "function",
+        F64(_) => "number",
+        IdentifierLiteral(_) => "identifier",
+        InstanceLiteral(_) => "instance",
+        StringLiteral(_) => "string",
+        None => "nil",
+    }
+}
+
+/// the kind of a value with its article, for error messages
+fn describe_type(literal: &Literal) -> &'static str {
+    match literal {
+        BoolLiteral(_) => "a boolean",
+        CallableLiteral(_) => "a function",
+        F64(_) => "a number",
+        IdentifierLiteral(_) => "an identifier",
+        InstanceLiteral(_) => "an instance",
+        StringLiteral(_) => "a string",
+        None => "nil",
+    }
+}
+
+pub fn is_truthy(literal: &Literal) -> bool {
     match literal {
         Literal::BoolLiteral(b) => *b,
         Literal::None => false,
@@ -400,20 +979,81 @@ fn is_truthy(literal: &Literal) -> bool {
     }
 }
 
-fn is_equal(left: Literal, right: Literal) -> bool {
+pub fn is_equal(left: Literal, right: Literal) -> bool {
     match (left, right) {
         (None, None) => true,
         (None, _) => false,
         (BoolLiteral(b1), BoolLiteral(b2)) => b1 == b2,
         (F64(f1), F64(f2)) => f1 == f2,
         (IdentifierLiteral(ident1), IdentifierLiteral(ident2)) => ident1 == ident2,
+        (CallableLiteral(callable1), CallableLiteral(callable2)) => callable1.is_equal(&callable2),
+        (InstanceLiteral(instance1), InstanceLiteral(instance2)) => {
+            instance1.id() == instance2.id()
+        }
         (StringLiteral(s1), StringLiteral(s2)) => s1 == s2,
         _ => false,
     }
 }
 
+/// Evaluate a get or call and the gets and calls its object or callee is made of, giving
+/// `Option::None` once a `?.` finds nil, so the rest of the chain, arguments included, isn't
+/// evaluated.
+fn evaluate_chain(
+    expr: &mut Expr,
+    environment: &mut Environment,
+) -> Result<Option<Literal>, Unwind> {
+    let optional = matches!(expr.1, ExprKind::OptionalGet { .. });
+    let value = match &mut expr.1 {
+        ExprKind::Call {
+            callee,
+            paren,
+            arguments,
+        } => {
+            let callee = match evaluate_chain(callee, environment)? {
+                Some(callee) => callee,
+                _ => return Ok(Option::None),
+            };
+
+            let mut func_args = Vec::new();
+            for argument in arguments {
+                func_args.push(argument.interpret(environment)?);
+            }
+
+            match callee {
+                CallableLiteral(function) => {
+                    if let Err(message) = function.check_arity(func_args.len()) {
+                        return Err(Unwind::Error(paren.clone(), message));
+                    }
+                    function.call(func_args, paren, environment)?
+                }
+                _ => {
+                    return Err(Unwind::Error(
+                        paren.clone(),
+                        localize(Message::NotCallable, &[]),
+                    ))
+                }
+            }
+        }
+        ExprKind::Get { object, name } | ExprKind::OptionalGet { object, name } => {
+            match evaluate_chain(object, environment)? {
+                Some(InstanceLiteral(mut instance)) => instance.get(name)?,
+                Some(None) if optional => return Ok(Option::None),
+                Some(_) => {
+                    return Err(Unwind::Error(
+                        name.clone(),
+                        "Only instances have properties.".into(),
+                    ))
+                }
+                _ => return Ok(Option::None),
+            }
+        }
+        _ => expr.interpret(environment)?,
+    };
+    Ok(Some(value))
+}
+
 pub fn resolve(id: usize, depth: usize, environment: &mut Environment) {
-    environment.locals.insert(id, depth);
+    environment.locals.borrow_mut().insert(id, depth);
 }
 
 fn lookup_variable(
@@ -421,19 +1061,19 @@ fn lookup_variable(
     id: usize,
     environment: &mut Environment,
 ) -> Result<Literal, (Token, Soo)> {
-    match environment.locals.get(&id) {
-        Some(distance) => Ok(environment.get_at(*distance, &name.lexeme).unwrap()),
+    match environment.resolved_depth(id) {
+        Some(distance) => Ok(environment.get_at(distance, &name.lexeme).unwrap()),
         _ => match environment
             .layers
             .get(0)
             .unwrap()
             .borrow_mut()
-            .get(&name.lexeme)
+            .get(&*name.lexeme)
         {
             Some(var) => Ok(var.to_owned()),
             _ => Err((
                 name.clone(),
-                format!("Unable to resolve global variable '{}'.", name.lexeme).into(),
+                localize(Message::UndefinedVariable, &[&name.lexeme]),
             )),
         },
     }
@@ -1,50 +1,190 @@
 #![feature(is_some_with, let_chains)]
 
 mod ast_display;
+mod ast_stats;
 mod callable;
 mod class;
 mod environment;
 mod expr;
+mod formatter;
 mod instance;
 mod interpreter;
+mod optimize;
 mod parser;
+mod program;
 mod resolver;
 mod scanner;
 mod stmt;
 mod token;
 mod token_type;
 mod utils;
+mod visit;
 
 use std::env;
 use std::error::Error;
 use std::fs;
 use std::io::{stdin, stdout, Write};
 use std::process;
+use std::thread;
+use std::time::Instant;
 
+use ast_stats::AstStats;
 use environment::Environment;
 use interpreter::interpret;
 use scanner::Scanner;
-use utils::Soo;
+use stmt::Stmt;
+use token::{Literal, Token};
+use token_type::TokenType;
+use utils::{paint, Color, Soo};
+
+const USAGE: &str = "Usage: rlox [--time] \
+                     [--fmt [--indent N|tab] | --parse-only | --check | --dump-locals] \
+                     [--warn-shadow] [--coerce] [--precision N] [--no-color] [--stats] \
+                     [--max-errors N] [--print-result] [--messages FILE] [script]";
+
+/// command line options controlling how a script is run
+#[derive(Default)]
+struct Options {
+    /// report how long each phase takes
+    time: bool,
+    /// stop after static analysis, without interpreting
+    check: bool,
+    /// warn when a local variable shadows one from an enclosing scope
+    warn_shadow: bool,
+    /// let `+` concatenate a string with a number
+    coerce: bool,
+    /// print what each variable reference resolved to instead of running
+    dump_locals: bool,
+    /// significant digits used when printing fractional numbers
+    precision: Option<usize>,
+    /// indentation used by `--fmt`, `formatter::DEFAULT_INDENT` when not given
+    indent: Option<String>,
+    /// never color diagnostics, even on a terminal
+    no_color: bool,
+    /// report how many statements and calls ran and how deeply scopes nested
+    stats: bool,
+    /// stop parsing after this many statements fail to parse
+    max_errors: Option<usize>,
+    /// print the value of an expression statement that ends the source
+    print_result: bool,
+}
+
+/// Every Lox call nests several native calls, which take far more stack than the main
+/// thread has in a debug build, so the interpreter runs on a thread with room for
+/// `environment::MAX_CALL_DEPTH` of them.
+const STACK_SIZE: usize = 1 << 30;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .expect("Unable to start the interpreter thread.");
+    // the thread has already reported its panic
+    if interpreter.join().is_err() {
+        process::exit(70);
+    }
+}
 
-    if args.len() > 2 {
-        println!("Usage: rlox [script]");
-    } else if args.len() == 2 {
-        if let Err(error) = run_file(&args[1]) {
+fn run_command() {
+    let mut options = Options::default();
+    let mut fmt = false;
+    let mut parse_only = false;
+    let mut args = Vec::new();
+
+    let mut cli = env::args().skip(1);
+    while let Some(arg) = cli.next() {
+        match arg.as_str() {
+            "--time" => options.time = true,
+            "--fmt" => fmt = true,
+            "--parse-only" => parse_only = true,
+            "--check" => options.check = true,
+            "--warn-shadow" => options.warn_shadow = true,
+            "--coerce" => options.coerce = true,
+            "--dump-locals" => options.dump_locals = true,
+            "--no-color" => options.no_color = true,
+            "--stats" => options.stats = true,
+            "--print-result" => options.print_result = true,
+            "--precision" => match cli.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => options.precision = Some(n),
+                _ => usage_error("'--precision' expects a positive number of digits."),
+            },
+            "--max-errors" => match cli.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => options.max_errors = Some(n),
+                _ => usage_error("'--max-errors' expects a positive number of errors."),
+            },
+            "--messages" => match cli.next() {
+                Some(path) => {
+                    let loaded = fs::read_to_string(&path)
+                        .map_err(|error| format!("Unable to read '{path}': {error}."))
+                        .and_then(|text| utils::load_catalog(&text));
+                    if let Err(message) = loaded {
+                        usage_error(&message);
+                    }
+                }
+                _ => usage_error("'--messages' expects a message catalog file."),
+            },
+            "--indent" => match cli.next().as_deref() {
+                Some("tab") => options.indent = Some("\t".to_owned()),
+                Some(n) => match n.parse() {
+                    Ok(n) if n > 0 => options.indent = Some(" ".repeat(n)),
+                    _ => usage_error("'--indent' expects a positive number of spaces or 'tab'."),
+                },
+                _ => usage_error("'--indent' expects a positive number of spaces or 'tab'."),
+            },
+            _ if arg.starts_with("--") => usage_error(&format!("Unknown option '{arg}'.")),
+            _ => args.push(arg),
+        }
+    }
+
+    utils::init_color(options.no_color);
+
+    let modes = [fmt, parse_only, options.check]
+        .iter()
+        .filter(|&&mode| mode)
+        .count();
+    if args.len() > 1 || (modes > 0 && args.is_empty()) || modes > 1 {
+        println!("{USAGE}");
+    } else if parse_only {
+        if let Err(error) = parse_file(&args[0]) {
+            println!("Error parsing file: {:?}", error);
+        }
+    } else if fmt {
+        let indent = options
+            .indent
+            .as_deref()
+            .unwrap_or(formatter::DEFAULT_INDENT);
+        if let Err(error) = format_file(&args[0], indent) {
+            println!("Error parsing file: {:?}", error);
+        }
+    } else if args.len() == 1 {
+        if let Err(error) = run_file(&args[0], &options) {
             println!("Error parsing file: {:?}", error);
         }
     } else {
-        run_prompt();
+        run_prompt(&options);
     }
 }
 
-fn run_file(path: &str) -> Result<(), Box<dyn Error>> {
+fn usage_error(message: &str) -> ! {
+    println!("{message}");
+    println!("{USAGE}");
+    process::exit(64);
+}
+
+fn run_file(path: &str, options: &Options) -> Result<(), Box<dyn Error>> {
     let text: String = fs::read_to_string(path)?.parse()?;
     let mut environment = Environment::new();
+    environment.warn_shadowing = options.warn_shadow;
+    environment.coerce_strings = options.coerce;
+    environment.precision = options.precision;
+    environment.stats = options.stats.then(Default::default);
+    // imports in the script are relative to it, and importing it again is a cycle
+    environment
+        .imports
+        .borrow_mut()
+        .push(fs::canonicalize(path)?);
 
-    let (had_error, had_runtime_error) = run(&text, &mut environment);
+    let (had_error, had_runtime_error) = run(&text, &mut environment, options);
 
     if had_error {
         process::exit(65);
@@ -56,44 +196,165 @@ fn run_file(path: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn run_prompt() {
+fn format_file(path: &str, indent: &str) -> Result<(), Box<dyn Error>> {
+    let text: String = fs::read_to_string(path)?.parse()?;
+
+    let (tokens, had_error, comments) = Scanner::new(&text).scan_tokens_and_comments();
+    if had_error {
+        process::exit(65);
+    }
+
+    // the program is parsed only to check that it is valid before formatting it
+    match parser::parse(tokens.clone(), None) {
+        Ok(_) => print!("{}", formatter::format(&tokens, &comments, indent)),
+        Err(_errors) => {
+            println!("Parse errors encountered.");
+            process::exit(65);
+        }
+    }
+
+    Ok(())
+}
+
+/// parse a file without running it and print how many nodes of each kind it has
+fn parse_file(path: &str) -> Result<(), Box<dyn Error>> {
+    let text: String = fs::read_to_string(path)?.parse()?;
+
+    let (tokens, had_error) = Scanner::new(&text).scan_tokens();
+    if had_error {
+        process::exit(65);
+    }
+
+    match parser::parse(tokens, None) {
+        Ok(statements) => {
+            let stats = AstStats::count(&statements);
+            println!("statements: {}", stats.statements);
+            println!("functions: {}", stats.functions);
+            println!("classes: {}", stats.classes);
+            println!("expressions: {}", stats.expressions);
+        }
+        Err(_errors) => {
+            println!("Parse errors encountered.");
+            process::exit(65);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_prompt(options: &Options) {
     let mut environment = Environment::new();
+    environment.warn_shadowing = options.warn_shadow;
+    environment.coerce_strings = options.coerce;
+    environment.precision = options.precision;
+    environment.stats = options.stats.then(Default::default);
     loop {
-        print!("> ");
+        print!("{}", prompt(&environment));
         stdout().flush().unwrap();
         let mut input = String::new();
         stdin().read_line(&mut input).expect("Input invalid");
         if input.is_empty() {
             break;
         }
-        run(&input, &mut environment);
+        // `:typex` is Lox code, not the command followed by `x`
+        let command = input
+            .trim_start()
+            .strip_prefix(":type")
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+        match command {
+            Some(expression) => print_type(expression, &mut environment),
+            _ => {
+                run(&input, &mut environment, options);
+            }
+        }
+    }
+}
+
+/// evaluate an expression entered after `:type` and print the name of its type
+fn print_type(expression: &str, environment: &mut Environment) {
+    let token = Token {
+        typ: TokenType::Identifier,
+        lexeme: ":type".into(),
+        literal: Literal::None,
+        line: 1,
+    };
+    match interpreter::eval_expression(expression, &token, environment) {
+        Ok(value) => println!("{}", interpreter::type_name(&value)),
+        Err((token, mut message)) => {
+            runtime_error(token.line, &mut message);
+            environment.call_stack.borrow_mut().clear();
+        }
     }
 }
 
-fn run(source: &str, environment: &mut Environment) -> (bool, bool) {
+/// the REPL prompt, showing how many globals the session has defined
+fn prompt(environment: &Environment) -> String {
+    format!("rlox[{}]> ", environment.user_global_count())
+}
+
+fn run(source: &str, environment: &mut Environment, options: &Options) -> (bool, bool) {
+    let start = Instant::now();
     let scanner = Scanner::new(source);
     let (tokens, had_error) = scanner.scan_tokens();
+    report_time(options.time, "scan", start);
 
     if had_error {
         return (had_error, false);
     }
 
-    match parser::parse(tokens) {
+    let start = Instant::now();
+    let parsed = parser::parse(tokens, options.max_errors);
+    report_time(options.time, "parse", start);
+
+    match parsed {
         Ok(mut statements) => {
             let mut had_error = false;
-            if resolver::resolve_statements(
+            let start = Instant::now();
+            let resolved = resolver::resolve_statements(
                 &mut statements,
                 environment,
                 &mut Vec::new(),
                 &mut Vec::new(),
+                &mut Vec::new(),
                 &mut had_error,
-            )
-            .is_err()
-                || had_error
-            {
+            );
+            report_time(options.time, "resolve", start);
+
+            if let Err((token, message)) = &resolved {
+                report(token.line, &format!(" at '{}'", token.lexeme), message);
+                // the REPL keeps this environment for its next line
+                resolver::discard_scopes(environment);
+            }
+
+            if resolved.is_err() || had_error {
                 (true, false)
+            } else if options.check {
+                (false, false)
+            } else if options.dump_locals {
+                resolver::dump_locals(&statements, environment);
+                (false, false)
             } else {
-                (false, interpret(statements, environment))
+                if options.print_result
+                    && let Some(Stmt::Expression { expression }) = statements.last_mut()
+                {
+                    let expression = expression.clone();
+                    *statements.last_mut().unwrap() = Stmt::Print { expression };
+                }
+
+                let start = Instant::now();
+                optimize::fold_constants(&mut statements);
+                report_time(options.time, "optimize", start);
+
+                let start = Instant::now();
+                let had_runtime_error = interpret(statements, environment);
+                report_time(options.time, "interpret", start);
+
+                if let Some(stats) = environment.stats_mut() {
+                    eprintln!("[stats] statements executed: {}", stats.statements);
+                    eprintln!("[stats] calls made: {}", stats.calls);
+                    eprintln!("[stats] max scope depth: {}", stats.max_scope_depth);
+                }
+                (false, had_runtime_error)
             }
         }
         Err(_errors) => {
@@ -103,14 +364,40 @@ fn run(source: &str, environment: &mut Environment) -> (bool, bool) {
     }
 }
 
+/// print the time elapsed since `start` for a phase when timing is enabled
+fn report_time(time: bool, phase: &str, start: Instant) {
+    if time {
+        eprintln!("[time] {phase}: {:?}", start.elapsed());
+    }
+}
+
 fn error(line: usize, message: &Soo) {
     report(line, "", message);
 }
 
 fn report(line: usize, location: &str, message: &Soo) {
-    println!("[line {}] Error{}: {}", line, location, message);
+    println!(
+        "{} {}{}: {}",
+        paint(&format!("[line {line}]"), Color::Cyan),
+        paint("Error", Color::Red),
+        location,
+        message
+    );
+}
+
+fn warning(line: usize, message: &Soo) {
+    println!(
+        "{} {}: {}",
+        paint(&format!("[line {line}]"), Color::Cyan),
+        paint("Warning", Color::Yellow),
+        message
+    );
 }
 
 fn runtime_error(line: usize, message: &mut Soo) {
-    println!("{}\n[line {}]", message, line);
+    println!(
+        "{}\n{}",
+        paint(&message.to_string(), Color::Red),
+        paint(&format!("[line {line}]"), Color::Cyan)
+    );
 }
@@ -0,0 +1,240 @@
+use std::mem;
+
+use crate::{
+    environment::Environment,
+    expr::{Expr, ExprKind},
+    interpreter::evaluate,
+    interpreter::is_truthy,
+    stmt::{Function, Stmt},
+    token::Literal,
+    token_type::TokenType,
+};
+
+/// Fold constant subexpressions into literals.
+///
+/// Operations that would fail or produce a non-finite number are left in place
+/// so that they still report their error (or result) when the program runs.
+pub fn fold_constants(statements: &mut [Stmt]) {
+    // constant expressions never look anything up, this only satisfies `evaluate`
+    let mut scratch = Environment::new();
+    for stmt in statements {
+        fold_stmt(stmt, &mut scratch);
+    }
+}
+
+fn fold_stmt(stmt: &mut Stmt, scratch: &mut Environment) {
+    match stmt {
+        Stmt::Assert {
+            keyword: _,
+            condition,
+            source: _,
+        } => fold_expr(condition, scratch),
+        Stmt::Block { statements } => {
+            for stmt in statements {
+                fold_stmt(stmt, scratch);
+            }
+        }
+        Stmt::Class {
+            name: _,
+            superclass: _,
+            fields,
+            methods,
+        } => {
+            for expr in fields
+                .iter_mut()
+                .filter_map(|field| field.initializer.as_mut())
+            {
+                fold_expr(expr, scratch);
+            }
+            for method in methods {
+                fold_function(method, scratch);
+            }
+        }
+        Stmt::Expression { expression } | Stmt::Print { expression } => {
+            fold_expr(expression, scratch)
+        }
+        Stmt::Function(function) => fold_function(function, scratch),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_ifs,
+            else_branch,
+        } => {
+            fold_expr(condition, scratch);
+            fold_stmt(then_branch, scratch);
+            for (condition, stmt) in else_ifs {
+                fold_expr(condition, scratch);
+                fold_stmt(stmt, scratch);
+            }
+            if let Some(stmt) = else_branch {
+                fold_stmt(stmt, scratch);
+            }
+        }
+        Stmt::Return {
+            keyword: _,
+            value: Some(expr),
+        }
+        | Stmt::Var {
+            name: _,
+            initializer: Some(expr),
+        } => fold_expr(expr, scratch),
+        Stmt::Import { .. } | Stmt::Return { .. } | Stmt::Var { .. } => {}
+        Stmt::While { condition, body } => {
+            fold_expr(condition, scratch);
+            fold_stmt(body, scratch);
+        }
+    }
+}
+
+fn fold_function(function: &mut Function, scratch: &mut Environment) {
+    for expr in function.defaults.iter_mut().flatten() {
+        fold_expr(expr, scratch);
+    }
+    for stmt in &mut function.body {
+        fold_stmt(stmt, scratch);
+    }
+}
+
+fn fold_expr(expr: &mut Expr, scratch: &mut Environment) {
+    match &mut expr.1 {
+        ExprKind::Assign { name: _, value } => fold_expr(value, scratch),
+        ExprKind::Binary {
+            left,
+            operator: _,
+            right,
+            operand_lines: _,
+        } => {
+            fold_expr(left, scratch);
+            fold_expr(right, scratch);
+            if is_literal(left) && is_literal(right) {
+                fold_into_literal(expr, scratch);
+            }
+        }
+        ExprKind::BlockExpr { statements, value } => {
+            for stmt in statements {
+                fold_stmt(stmt, scratch);
+            }
+            fold_expr(value, scratch);
+        }
+        ExprKind::Call {
+            callee,
+            paren: _,
+            arguments,
+        } => {
+            fold_expr(callee, scratch);
+            for argument in arguments {
+                fold_expr(argument, scratch);
+            }
+        }
+        ExprKind::Get { object, name: _ } | ExprKind::OptionalGet { object, name: _ } => {
+            fold_expr(object, scratch)
+        }
+        ExprKind::Grouping { expression } => {
+            fold_expr(expression, scratch);
+            if is_literal(expression) {
+                fold_into_literal(expr, scratch);
+            }
+        }
+        ExprKind::LiteralExpr { value: _ } => {}
+        ExprKind::Logical {
+            left,
+            operator,
+            right,
+        } => {
+            fold_expr(left, scratch);
+            fold_expr(right, scratch);
+
+            if let ExprKind::LiteralExpr { value } = &left.1 {
+                let short_circuits = match operator.typ {
+                    TokenType::Or => is_truthy(value),
+                    _ => !is_truthy(value),
+                };
+
+                // either the left operand is the result, or the right one always is
+                let result = if short_circuits {
+                    mem::replace(left.as_mut(), placeholder())
+                } else {
+                    mem::replace(right.as_mut(), placeholder())
+                };
+                *expr = result;
+            }
+        }
+        ExprKind::Set {
+            object,
+            name: _,
+            value,
+        } => {
+            fold_expr(object, scratch);
+            fold_expr(value, scratch);
+        }
+        ExprKind::Super { .. } | ExprKind::This { .. } | ExprKind::Variable { .. } => {}
+        ExprKind::Unary { operator: _, right } => {
+            fold_expr(right, scratch);
+            if is_literal(right) {
+                fold_into_literal(expr, scratch);
+            }
+        }
+    }
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(expr.1, ExprKind::LiteralExpr { value: _ })
+}
+
+fn placeholder() -> Expr {
+    Expr(
+        0,
+        ExprKind::LiteralExpr {
+            value: Literal::None,
+        },
+    )
+}
+
+/// replace an expression over literal operands with its value, unless evaluating it fails
+fn fold_into_literal(expr: &mut Expr, scratch: &mut Environment) {
+    match evaluate(expr, scratch) {
+        Ok(Literal::F64(f)) if !f.is_finite() => {}
+        Ok(value) => expr.1 = ExprKind::LiteralExpr { value },
+        Err(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, scanner::Scanner};
+
+    /// fold `expression` and show what is left of it
+    fn fold(expression: &str) -> String {
+        let (tokens, had_error) = Scanner::new(&format!("print {expression};")).scan_tokens();
+        assert!(!had_error);
+        let mut statements = parser::parse(tokens, None).unwrap_or_else(|_| panic!("parse error"));
+        fold_constants(&mut statements);
+        match &statements[..] {
+            [Stmt::Print { expression }] => expression.to_string(),
+            _ => panic!("expected a print statement"),
+        }
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        assert_eq!(fold("2 + 3 * 4"), "14");
+        assert_eq!(fold("-(1 + 1)"), "-2");
+    }
+
+    #[test]
+    fn folds_logical_operators_to_an_operand() {
+        assert_eq!(fold("(1 + 2) and nil or \"x\""), "x");
+    }
+
+    #[test]
+    fn leaves_failing_or_infinite_operations() {
+        assert_eq!(fold("1 / 0"), "(/ 1 0)");
+        assert_eq!(fold("\"a\" - 1"), "(- a 1)");
+    }
+
+    #[test]
+    fn leaves_variables() {
+        assert_eq!(fold("x + 2 * 3"), "(+ x 6)");
+    }
+}
@@ -2,12 +2,12 @@ use std::iter::Peekable;
 use std::slice::Iter;
 
 use crate::expr::ExprKind;
-use crate::report;
-use crate::stmt::Stmt;
+use crate::stmt::{Field, Stmt};
 use crate::token::Literal;
 use crate::token_type::TokenType::{self, *};
-use crate::utils::{ExprId, Soo};
+use crate::utils::{localize, ExprId, Message, Soo};
 use crate::{expr::Expr, token::Token};
+use crate::{formatter, report};
 
 // parameters: token iterator, and a series of TokenType variants separated by |
 // return option of next token
@@ -27,7 +27,11 @@ macro_rules! match_types {
     };
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<(Token, Soo)>> {
+/// parse a program, giving up after `max_errors` statements fail to parse when it is given
+pub fn parse(
+    tokens: Vec<Token>,
+    max_errors: Option<usize>,
+) -> Result<Vec<Stmt>, Vec<(Token, Soo)>> {
     let line_count = match tokens.last() {
         Some(token) => token.line,
         None => 0,
@@ -45,6 +49,11 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<(Token, Soo)>> {
             Ok(stmt) => statements.push(stmt),
             Err(error) => errors.push(error),
         };
+
+        if matches!(max_errors, Some(max) if errors.len() >= max) && token_iter.peek().is_some() {
+            println!("too many errors; aborting.");
+            break;
+        }
     }
 
     if errors.is_empty() && !had_error {
@@ -54,6 +63,35 @@ pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<(Token, Soo)>> {
     }
 }
 
+/// parse source that must be a single expression, optionally followed by a semicolon
+pub fn parse_expression(tokens: Vec<Token>) -> Result<Expr, (Token, Soo)> {
+    let line_count = match tokens.last() {
+        Some(token) => token.line,
+        None => 0,
+    };
+
+    let token_iter = &mut tokens.iter().peekable();
+    if token_iter.peek().is_none() {
+        return Err(error(line_count, token_iter, "Expected expression.".into()));
+    }
+
+    let mut had_error = false;
+    let expression = expression(&mut ExprId::new(), line_count, token_iter, &mut had_error)?;
+    match_types!(token_iter, Semicolon);
+    if token_iter.peek().is_some() {
+        return Err(error(
+            line_count,
+            token_iter,
+            "Expected a single expression.".into(),
+        ));
+    }
+    if had_error {
+        // the error has been reported already
+        return Err((generate_eof(line_count), "Invalid expression.".into()));
+    }
+    Ok(expression)
+}
+
 fn declaration(
     id: &mut ExprId,
     line_count: usize,
@@ -70,7 +108,11 @@ fn declaration(
         _ => statement(id, line_count, tokens, had_error),
     };
 
-    if result.is_err() {
+    // the token an error is reported at has been consumed, so when it is the `;` ending
+    // the statement there is nothing left of the statement to skip
+    if let Err((token, _)) = &result
+        && token.typ != Semicolon
+    {
         synchronize(line_count, tokens);
     }
     result
@@ -119,9 +161,18 @@ fn class_declaration(
         tokens,
     )?;
 
+    let mut fields = Vec::new();
     let mut methods = Vec::new();
     while !check(RightBrace, tokens) && tokens.peek().is_some() {
-        methods.push(function("method", id, line_count, tokens, had_error)?);
+        if check(Var, tokens) {
+            if let Stmt::Var { name, initializer } =
+                var_declaration(id, line_count, tokens, had_error)?
+            {
+                fields.push(Field { name, initializer });
+            }
+        } else {
+            methods.push(function("method", id, line_count, tokens, had_error)?);
+        }
     }
 
     consume(
@@ -135,6 +186,7 @@ fn class_declaration(
     Ok(Stmt::Class {
         name,
         superclass,
+        fields,
         methods,
     })
 }
@@ -163,6 +215,7 @@ fn function(
     )?;
 
     let mut parameters = Vec::new();
+    let mut defaults = Vec::new();
     if !check(RightParen, tokens) {
         loop {
             if parameters.len() >= 255 {
@@ -185,6 +238,20 @@ fn function(
                 .to_owned(),
             );
 
+            if match_types!(tokens, Equal).is_some() {
+                defaults.push(Some(expression(id, line_count, tokens, had_error)?));
+            } else {
+                if defaults.last().is_some_and(|default| default.is_some()) {
+                    report_error(
+                        tokens,
+                        line_count,
+                        had_error,
+                        "Parameters without a default can't follow ones with a default.".into(),
+                    );
+                }
+                defaults.push(None);
+            }
+
             if match_types!(tokens, Comma).is_none() {
                 break;
             }
@@ -212,7 +279,9 @@ fn function(
     let body = block(id, line_count, tokens, had_error)?;
     Ok(crate::stmt::Function {
         name: name.to_owned(),
+        id: id.next(),
         params: parameters,
+        defaults,
         body,
     })
 }
@@ -277,14 +346,23 @@ fn statement(
 ) -> Result<Stmt, (Token, Soo)> {
     match tokens.peek() {
         Some(next_token) => match next_token.typ {
+            Assert => assert_statement(id, line_count, tokens, had_error),
             For => for_statement(id, line_count, tokens, had_error),
             If => if_statement(id, line_count, tokens, had_error),
+            Import => import_statement(line_count, tokens),
             Print => print_statement(id, line_count, tokens, had_error),
             Return => return_statement(id, line_count, tokens, had_error),
             While => while_statement(id, line_count, tokens, had_error),
             LeftBrace => Ok(Stmt::Block {
                 statements: block(id, line_count, tokens, had_error)?,
             }),
+            // a stray semicolon is an empty statement
+            Semicolon => {
+                tokens.next();
+                Ok(Stmt::Block {
+                    statements: Vec::new(),
+                })
+            }
             _ => expression_statement(id, line_count, tokens, had_error),
         },
         None => Err(error(line_count, tokens, "Expected a statement.".into())),
@@ -381,7 +459,36 @@ fn if_statement(
     had_error: &mut bool,
 ) -> Result<Stmt, (Token, Soo)> {
     tokens.next();
+    let (condition, then_branch) = if_arm(id, line_count, tokens, had_error)?;
+
+    // `else if` arms are collected in a loop instead of parsed as nested ifs, so a long
+    // chain doesn't recurse once per arm here or in any later pass over the tree
+    let mut else_ifs = Vec::new();
+    let mut else_branch = None;
+    while match_types!(tokens, Else).is_some() {
+        if match_types!(tokens, If).is_some() {
+            else_ifs.push(if_arm(id, line_count, tokens, had_error)?);
+        } else {
+            else_branch = Some(Box::new(statement(id, line_count, tokens, had_error)?));
+            break;
+        }
+    }
 
+    Ok(Stmt::If {
+        condition: Box::new(condition),
+        then_branch: Box::new(then_branch),
+        else_ifs,
+        else_branch,
+    })
+}
+
+/// the `(condition) statement` following an `if`
+fn if_arm(
+    id: &mut ExprId,
+    line_count: usize,
+    tokens: &mut Peekable<Iter<Token>>,
+    had_error: &mut bool,
+) -> Result<(Expr, Stmt), (Token, Soo)> {
     match tokens.next() {
         Some(left_paren) => match left_paren.typ {
             LeftParen => {
@@ -390,18 +497,7 @@ fn if_statement(
                     Some(right_paren) => match right_paren.typ {
                         RightParen => {
                             let then_branch = statement(id, line_count, tokens, had_error)?;
-                            let else_token = match_types!(tokens, Else);
-                            let else_branch = match else_token {
-                                Some(_) => Some(statement(id, line_count, tokens, had_error)?),
-                                _ => None,
-                            };
-
-                            Ok(Stmt::If {
-                                condition: Box::new(condition),
-                                then_branch: Box::new(then_branch),
-                                else_branch: else_branch
-                                    .and_then(|else_stmt| Some(Box::new(else_stmt))),
-                            })
+                            Ok((condition, then_branch))
                         }
                         _ => Err(error(
                             line_count,
@@ -426,6 +522,80 @@ fn if_statement(
     }
 }
 
+fn assert_statement(
+    id: &mut ExprId,
+    line_count: usize,
+    tokens: &mut Peekable<Iter<Token>>,
+    had_error: &mut bool,
+) -> Result<Stmt, (Token, Soo)> {
+    let keyword = tokens.next().unwrap().clone();
+    let condition = expression(id, line_count, tokens, had_error)?;
+
+    // `assert(x)` reads like a call, the parentheses aren't part of the condition
+    let source = match &condition.1 {
+        ExprKind::Grouping { expression } => formatter::format_expr(expression),
+        _ => formatter::format_expr(&condition),
+    };
+
+    consume(
+        Semicolon,
+        "Expected ';' after assertion, instead found end of file.".into(),
+        "Expected ';' after assertion.".into(),
+        line_count,
+        tokens,
+    )?;
+
+    Ok(Stmt::Assert {
+        keyword,
+        condition: Box::new(condition),
+        source,
+    })
+}
+
+fn import_statement(
+    line_count: usize,
+    tokens: &mut Peekable<Iter<Token>>,
+) -> Result<Stmt, (Token, Soo)> {
+    tokens.next();
+
+    let path = consume(
+        StringToken,
+        "Expected a file path after 'import', instead found end of file.".into(),
+        "Expected a file path after 'import'.".into(),
+        line_count,
+        tokens,
+    )?
+    .clone();
+
+    // `as` is only special here, so it stays usable as a name everywhere else
+    let alias = match tokens.peek() {
+        Some(token) if token.typ == Identifier && &*token.lexeme == "as" => {
+            tokens.next();
+            Some(
+                consume(
+                    Identifier,
+                    "Expected a name after 'as', instead found end of file.".into(),
+                    "Expected a name after 'as'.".into(),
+                    line_count,
+                    tokens,
+                )?
+                .clone(),
+            )
+        }
+        _ => None,
+    };
+
+    consume(
+        Semicolon,
+        "Expected ';' after import, instead found end of file.".into(),
+        "Expected ';' after import.".into(),
+        line_count,
+        tokens,
+    )?;
+
+    Ok(Stmt::Import { path, alias })
+}
+
 fn print_statement(
     id: &mut ExprId,
     line_count: usize,
@@ -574,11 +744,10 @@ fn expression_statement(
                 "Expected ';' after expression.".into(),
             )),
         },
-        None => Err(error(
-            line_count,
-            tokens,
-            "Expected ';' after expression, instead found end of file.".into(),
-        )),
+        // the last statement of the source may leave off its semicolon
+        None => Ok(Stmt::Expression {
+            expression: Box::new(expression),
+        }),
     }
 }
 
@@ -687,10 +856,12 @@ fn equality(
     tokens: &mut Peekable<Iter<Token>>,
     had_error: &mut bool,
 ) -> Result<Expr, (Token, Soo)> {
+    let left_line = peek_line(line_count, tokens);
     let mut expr = comparison(id, line_count, tokens, had_error)?;
 
     while let Some(operator) = match_types!(tokens, BangEqual | EqualEqual) {
         let operator = operator.to_owned();
+        let right_line = peek_line(line_count, tokens);
         let right = comparison(id, line_count, tokens, had_error)?;
         expr = Expr(
             id.next(),
@@ -698,6 +869,7 @@ fn equality(
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                operand_lines: (left_line, right_line),
             },
         );
     }
@@ -711,10 +883,12 @@ fn comparison(
     tokens: &mut Peekable<Iter<Token>>,
     had_error: &mut bool,
 ) -> Result<Expr, (Token, Soo)> {
+    let left_line = peek_line(line_count, tokens);
     let mut expr = term(id, line_count, tokens, had_error)?;
 
     while let Some(operator) = match_types!(tokens, Greater | GreaterEqual | Less | LessEqual) {
         let operator = operator.to_owned();
+        let right_line = peek_line(line_count, tokens);
         let right = term(id, line_count, tokens, had_error)?;
         expr = Expr(
             id.next(),
@@ -722,6 +896,7 @@ fn comparison(
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                operand_lines: (left_line, right_line),
             },
         );
     }
@@ -735,10 +910,12 @@ fn term(
     tokens: &mut Peekable<Iter<Token>>,
     had_error: &mut bool,
 ) -> Result<Expr, (Token, Soo)> {
+    let left_line = peek_line(line_count, tokens);
     let mut expr = factor(id, line_count, tokens, had_error);
 
     while let Some(operator) = match_types!(tokens, Minus | Plus) {
         let operator = operator.to_owned();
+        let right_line = peek_line(line_count, tokens);
         let right = factor(id, line_count, tokens, had_error);
         expr = Ok(Expr(
             id.next(),
@@ -746,6 +923,7 @@ fn term(
                 left: Box::new(expr?),
                 operator,
                 right: Box::new(right?),
+                operand_lines: (left_line, right_line),
             },
         ));
     }
@@ -759,10 +937,12 @@ fn factor(
     tokens: &mut Peekable<Iter<Token>>,
     had_error: &mut bool,
 ) -> Result<Expr, (Token, Soo)> {
+    let left_line = peek_line(line_count, tokens);
     let mut expr = unary(id, line_count, tokens, had_error);
 
     while let Some(operator) = match_types!(tokens, Slash | Star) {
         let operator = operator.to_owned();
+        let right_line = peek_line(line_count, tokens);
         let right = unary(id, line_count, tokens, had_error);
         expr = Ok(Expr(
             id.next(),
@@ -770,6 +950,7 @@ fn factor(
                 left: Box::new(expr?),
                 operator,
                 right: Box::new(right?),
+                operand_lines: (left_line, right_line),
             },
         ));
     }
@@ -824,6 +1005,21 @@ fn call(
                     name: name.to_owned(),
                 },
             );
+        } else if match_types!(tokens, QuestionDot).is_some() {
+            let name = consume(
+                Identifier,
+                "Expected property name after '?.', instead found end of file.".into(),
+                "Expected property name after '?.'.".into(),
+                line_count,
+                tokens,
+            )?;
+            expr = Expr(
+                id.next(),
+                ExprKind::OptionalGet {
+                    object: Box::new(expr),
+                    name: name.to_owned(),
+                },
+            );
         } else {
             break;
         }
@@ -852,6 +1048,20 @@ fn finish_call(
                 );
             }
 
+            // `name: value` is a common mistake from languages with keyword arguments
+            let mut lookahead = tokens.clone();
+            if lookahead
+                .next()
+                .is_some_and(|token| token.typ == Identifier)
+                && lookahead.next().is_some_and(|token| token.typ == Colon)
+            {
+                return Err(error(
+                    line_count,
+                    tokens,
+                    "Named arguments are not supported.".into(),
+                ));
+            }
+
             arguments.push(expression(id, line_count, tokens, had_error)?);
             if !match_types!(tokens, Comma).is_some() {
                 break;
@@ -941,6 +1151,7 @@ fn primary(
                     name: token.to_owned(),
                 },
             )),
+            LeftBrace => block_expression(id, line_count, tokens, had_error),
             LeftParen => {
                 let expr = expression(id, line_count, tokens, had_error);
 
@@ -965,12 +1176,77 @@ fn primary(
                     )),
                 }
             }
-            _ => Err((token.clone(), "Expected expression.".into())),
+            _ => {
+                let message = localize(Message::ExpectedExpression, &[]);
+                report(token.line, &format!(" at '{}'", token.lexeme), &message);
+                Err((token.clone(), message))
+            }
         },
-        None => Err((
-            generate_eof(line_count),
-            "Expected expression, instead found end of file.".into(),
-        )),
+        None => {
+            let message = "Expected expression, instead found end of file.".into();
+            report(line_count, " at end", &message);
+            Err((generate_eof(line_count), message))
+        }
+    }
+}
+
+/// the declarations of a block followed by a final expression without a semicolon,
+/// the opening brace has already been consumed
+fn block_expression(
+    id: &mut ExprId,
+    line_count: usize,
+    tokens: &mut Peekable<Iter<Token>>,
+    had_error: &mut bool,
+) -> Result<Expr, (Token, Soo)> {
+    let mut statements = Vec::new();
+
+    loop {
+        match tokens.peek().map(|token| token.typ) {
+            Some(RightBrace) => {
+                return Err(error(
+                    line_count,
+                    tokens,
+                    "Expected a final expression before '}' in block expression.".into(),
+                ))
+            }
+            Some(
+                Assert | Class | Fun | Import | Var | For | If | Print | Return | While | LeftBrace
+                | Semicolon,
+            ) => {
+                statements.push(declaration(id, line_count, tokens, had_error)?);
+            }
+            Some(_) => {
+                let expr = expression(id, line_count, tokens, had_error)?;
+                if match_types!(tokens, Semicolon).is_some() {
+                    statements.push(Stmt::Expression {
+                        expression: Box::new(expr),
+                    });
+                    continue;
+                }
+
+                consume(
+                    RightBrace,
+                    "Expected '}' after block expression, instead found end of file.".into(),
+                    "Expected '}' after the final expression of a block.".into(),
+                    line_count,
+                    tokens,
+                )?;
+                return Ok(Expr(
+                    id.next(),
+                    ExprKind::BlockExpr {
+                        statements,
+                        value: Box::new(expr),
+                    },
+                ));
+            }
+            None => {
+                return Err(error(
+                    line_count,
+                    tokens,
+                    "Expected '}' after block expression, instead found end of file.".into(),
+                ))
+            }
+        }
     }
 }
 
@@ -1000,6 +1276,11 @@ fn check(typ: TokenType, tokens: &mut Peekable<Iter<Token>>) -> bool {
     }
 }
 
+/// the line of the next token, where an expression about to be parsed starts
+fn peek_line(line_count: usize, tokens: &mut Peekable<Iter<Token>>) -> usize {
+    tokens.peek().map(|token| token.line).unwrap_or(line_count)
+}
+
 fn error(line_count: usize, tokens: &mut Peekable<Iter<Token>>, message: Soo) -> (Token, Soo) {
     match tokens.next() {
         Some(token) => {
@@ -1020,7 +1301,9 @@ fn synchronize(_line_count: usize, tokens: &mut Peekable<Iter<Token>>) {
             _ => {
                 if let Some(token) = tokens.peek() {
                     match token.typ {
-                        Class | Fun | Var | For | If | While | Print | Return => return,
+                        Assert | Class | Fun | Import | Var | For | If | While | Print | Return => {
+                            return
+                        }
                         _ => {}
                     }
                 }
@@ -1032,7 +1315,7 @@ fn synchronize(_line_count: usize, tokens: &mut Peekable<Iter<Token>>) {
 fn generate_eof(line_count: usize) -> Token {
     Token {
         typ: Eof,
-        lexeme: String::new(),
+        lexeme: "".into(),
         literal: Literal::None,
         line: line_count,
     }
@@ -0,0 +1,106 @@
+// an API for embedding, which only the tests below use so far
+#![cfg_attr(not(test), allow(dead_code))]
+
+use std::collections::HashMap;
+
+use crate::{
+    environment::Environment, interpreter::interpret, optimize, parser, report, resolver,
+    scanner::Scanner, stmt::Stmt,
+};
+
+/// Why a program failed, its diagnostics have already been reported.
+#[derive(Debug)]
+pub enum RloxError {
+    /// scanning, parsing or resolving failed
+    Compile,
+    /// the program stopped with a runtime error
+    Runtime,
+}
+
+/// A scanned, parsed and resolved program that can be run any number of times.
+pub struct Program {
+    statements: Vec<Stmt>,
+    locals: HashMap<usize, usize>,
+}
+
+/// Check a program once so embedders running it repeatedly skip the front end.
+pub fn compile(source: &str) -> Result<Program, RloxError> {
+    let (tokens, had_error) = Scanner::new(source).scan_tokens();
+    if had_error {
+        return Err(RloxError::Compile);
+    }
+
+    let mut statements = parser::parse(tokens, None).map_err(|_| RloxError::Compile)?;
+
+    // resolution only depends on the program's own scopes, not on any globals
+    let mut scratch = Environment::new();
+    let mut had_error = false;
+    let resolved = resolver::resolve_statements(
+        &mut statements,
+        &mut scratch,
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut Vec::new(),
+        &mut had_error,
+    );
+    if let Err((token, message)) = &resolved {
+        report(token.line, &format!(" at '{}'", token.lexeme), message);
+    }
+    if resolved.is_err() || had_error {
+        return Err(RloxError::Compile);
+    }
+    optimize::fold_constants(&mut statements);
+
+    Ok(Program {
+        statements,
+        locals: scratch.locals.take(),
+    })
+}
+
+impl Program {
+    /// interpreting consumes the statements, so each run works on its own copy
+    pub fn run(&self, environment: &mut Environment) -> Result<(), RloxError> {
+        environment.locals.borrow_mut().extend(&self.locals);
+
+        if interpret(self.statements.clone(), environment) {
+            Err(RloxError::Runtime)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Literal;
+
+    fn global(environment: &Environment, name: &str) -> Option<f64> {
+        match environment.layers[0].borrow().get(name) {
+            Some(Literal::F64(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn runs_one_program_twice() {
+        let program = compile("{ var step = 1 + 1; count = count + step; }").unwrap();
+        let mut environment = Environment::new();
+        environment.define("count", Literal::F64(0.0));
+
+        program.run(&mut environment).unwrap();
+        program.run(&mut environment).unwrap();
+        assert_eq!(global(&environment, "count"), Some(4.0));
+    }
+
+    #[test]
+    fn reports_compile_and_runtime_errors() {
+        assert!(matches!(compile("print ;"), Err(RloxError::Compile)));
+
+        let program = compile("print missing;").unwrap();
+        assert!(matches!(
+            program.run(&mut Environment::new()),
+            Err(RloxError::Runtime)
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     environment::Environment,
@@ -7,6 +7,8 @@ use crate::{
     stmt::{Function, Stmt},
     token::Token,
     utils::Soo,
+    visit::{walk_expr, walk_stmts, Visitor},
+    warning,
 };
 
 #[derive(Eq, PartialEq)]
@@ -27,6 +29,7 @@ trait Resolver {
         environment: &mut Environment,
         function_stack: &mut Vec<FunctionType>,
         class_stack: &mut Vec<ClassType>,
+        declared_later: &mut Vec<HashSet<String>>,
         had_error: &mut bool,
     ) -> Result<(), (Token, Soo)>;
 }
@@ -37,24 +40,28 @@ impl Resolver for Stmt {
         environment: &mut Environment,
         function_stack: &mut Vec<FunctionType>,
         class_stack: &mut Vec<ClassType>,
+        declared_later: &mut Vec<HashSet<String>>,
         had_error: &mut bool,
     ) -> Result<(), (Token, Soo)> {
         match self {
             Stmt::Block { statements } => {
-                begin_scope(environment);
+                begin_scope(environment, declared_later);
+                note_later_declarations(statements, declared_later);
                 resolve_statements(
                     statements,
                     environment,
                     function_stack,
                     class_stack,
+                    declared_later,
                     had_error,
                 )?;
-                end_scope(environment);
+                end_scope(environment, declared_later);
                 Ok(())
             }
             Stmt::Class {
                 name,
                 superclass,
+                fields,
                 methods,
             } => {
                 class_stack.push(ClassType::Class);
@@ -79,9 +86,15 @@ impl Resolver for Stmt {
                         _ => panic!("Superclass was not a variable"),
                     }
 
-                    expr.resolve(environment, function_stack, class_stack, had_error)?;
+                    expr.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
 
-                    begin_scope(environment);
+                    begin_scope(environment, declared_later);
                     environment
                         .scopes
                         .last_mut()
@@ -89,15 +102,28 @@ impl Resolver for Stmt {
                         .insert("super".to_string(), true);
                 }
 
-                begin_scope(environment);
+                begin_scope(environment, declared_later);
                 environment
                     .scopes
                     .last_mut()
                     .unwrap()
                     .insert("this".to_owned(), true);
 
+                // field initializers run in the scope binding `this`, outside any function
+                for field in fields {
+                    if let Some(expr) = &mut field.initializer {
+                        expr.resolve(
+                            environment,
+                            function_stack,
+                            class_stack,
+                            declared_later,
+                            had_error,
+                        )?;
+                    }
+                }
+
                 for method in methods {
-                    let declaration = if method.name.lexeme == "init" {
+                    let declaration = if &*method.name.lexeme == "init" {
                         FunctionType::Initializer
                     } else {
                         FunctionType::Method
@@ -107,18 +133,22 @@ impl Resolver for Stmt {
                     function_stack.pop();
                 }
 
-                end_scope(environment);
+                end_scope(environment, declared_later);
 
                 if superclass.is_some() {
-                    end_scope(environment);
+                    end_scope(environment, declared_later);
                 }
 
                 class_stack.pop();
                 Ok(())
             }
-            Stmt::Expression { expression } => {
-                expression.resolve(environment, function_stack, class_stack, had_error)
-            }
+            Stmt::Expression { expression } => expression.resolve(
+                environment,
+                function_stack,
+                class_stack,
+                declared_later,
+                had_error,
+            ),
             Stmt::Function(function) => {
                 declare(&mut function.name, environment, had_error);
                 define(&mut function.name, environment);
@@ -132,24 +162,69 @@ impl Resolver for Stmt {
                     had_error,
                 )?;
                 function_stack.pop();
+                mark_tail_calls(&function.body, &function.name.lexeme, environment);
 
                 Ok(())
             }
             Stmt::If {
                 condition,
                 then_branch,
+                else_ifs,
                 else_branch,
             } => {
-                condition.resolve(environment, function_stack, class_stack, had_error)?;
-                then_branch.resolve(environment, function_stack, class_stack, had_error)?;
+                let arms = else_ifs
+                    .iter_mut()
+                    .map(|(condition, stmt)| (condition, stmt));
+                for (condition, stmt) in [(&mut **condition, &mut **then_branch)]
+                    .into_iter()
+                    .chain(arms)
+                {
+                    condition.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
+                    stmt.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
+                }
                 if let Some(stmt) = else_branch {
-                    stmt.resolve(environment, function_stack, class_stack, had_error)?;
+                    stmt.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
+                }
+                Ok(())
+            }
+            // the imported file is resolved on its own when it runs
+            Stmt::Import { path: _, alias } => {
+                if let Some(alias) = alias {
+                    declare(alias, environment, had_error);
+                    define(alias, environment);
                 }
                 Ok(())
             }
-            Stmt::Print { expression } => {
-                expression.resolve(environment, function_stack, class_stack, had_error)
+            Stmt::Assert {
+                keyword: _,
+                condition: expression,
+                source: _,
             }
+            | Stmt::Print { expression } => expression.resolve(
+                environment,
+                function_stack,
+                class_stack,
+                declared_later,
+                had_error,
+            ),
             Stmt::Return { keyword, value } => {
                 if function_stack.is_empty() {
                     error(keyword.line, &("Can't return from top-level code.".into()));
@@ -167,21 +242,45 @@ impl Resolver for Stmt {
                         *had_error = true;
                     }
 
-                    expr.resolve(environment, function_stack, class_stack, had_error)?;
+                    expr.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
                 }
                 Ok(())
             }
             Stmt::Var { name, initializer } => {
                 declare(name, environment, had_error);
                 if let Some(expr) = initializer {
-                    expr.resolve(environment, function_stack, class_stack, had_error)?;
+                    expr.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
                 }
                 define(name, environment);
                 Ok(())
             }
             Stmt::While { condition, body } => {
-                condition.resolve(environment, function_stack, class_stack, had_error)?;
-                body.resolve(environment, function_stack, class_stack, had_error)
+                condition.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
+                body.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )
             }
         }
     }
@@ -193,58 +292,158 @@ impl Resolver for Expr {
         environment: &mut Environment,
         function_stack: &mut Vec<FunctionType>,
         class_stack: &mut Vec<ClassType>,
+        declared_later: &mut Vec<HashSet<String>>,
         had_error: &mut bool,
     ) -> Result<(), (Token, Soo)> {
         match &mut self.1 {
             ExprKind::Assign { name, value } => {
-                value.resolve(environment, function_stack, class_stack, had_error)?;
+                value.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
                 let name = name.clone();
                 resolve_local(self.0, &name, environment)
-            },
+            }
             ExprKind::Binary {
                 left,
                 operator: _,
                 right,
+                operand_lines: _,
             } => {
-                left.resolve(environment, function_stack, class_stack, had_error)?;
-                right.resolve(environment, function_stack, class_stack, had_error)
-            },
+                left.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
+                right.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )
+            }
+            ExprKind::BlockExpr { statements, value } => {
+                begin_scope(environment, declared_later);
+                note_later_declarations(statements, declared_later);
+                resolve_statements(
+                    statements,
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
+                value.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
+                end_scope(environment, declared_later);
+                Ok(())
+            }
             ExprKind::Call {
                 callee,
                 paren: _,
                 arguments,
             } => {
-                callee.resolve(environment, function_stack, class_stack, had_error)?;
+                callee.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
 
                 for argument in arguments {
-                    argument.resolve(environment, function_stack, class_stack, had_error)?;
+                    argument.resolve(
+                        environment,
+                        function_stack,
+                        class_stack,
+                        declared_later,
+                        had_error,
+                    )?;
                 }
 
                 Ok(())
-            },
-            ExprKind::Get { object, name: _ } => object.resolve(environment, function_stack, class_stack, had_error),
-            ExprKind::Grouping { expression } => expression.resolve(environment, function_stack, class_stack, had_error),
+            }
+            ExprKind::Get { object, name: _ } | ExprKind::OptionalGet { object, name: _ } => object
+                .resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                ),
+            ExprKind::Grouping { expression } => expression.resolve(
+                environment,
+                function_stack,
+                class_stack,
+                declared_later,
+                had_error,
+            ),
             ExprKind::LiteralExpr { value: _ } => Ok(()),
             ExprKind::Logical {
                 left,
                 operator: _,
                 right,
             } => {
-                left.resolve(environment, function_stack, class_stack, had_error)?;
-                right.resolve(environment, function_stack, class_stack, had_error)
-            },
-            ExprKind::Set { object, name: _, value } => {
-                value.resolve(environment, function_stack, class_stack, had_error)?;
-                object.resolve(environment, function_stack, class_stack, had_error)
+                left.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
+                right.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )
+            }
+            ExprKind::Set {
+                object,
+                name: _,
+                value,
+            } => {
+                value.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )?;
+                object.resolve(
+                    environment,
+                    function_stack,
+                    class_stack,
+                    declared_later,
+                    had_error,
+                )
             }
             ExprKind::Super { keyword, method: _ } => {
                 match class_stack.last() {
                     None => {
-                        error(keyword.line, &("Can't use 'super' outside of a class.".into()));
+                        error(
+                            keyword.line,
+                            &("Can't use 'super' outside of a class.".into()),
+                        );
                         *had_error = true;
                     }
                     Some(ClassType::Class) => {
-                        error(keyword.line, &("Can't use 'super' in a class with no superclass.".into()));
+                        error(
+                            keyword.line,
+                            &("Can't use 'super' in a class with no superclass.".into()),
+                        );
                         *had_error = true;
                     }
                     _ => {}
@@ -254,15 +453,40 @@ impl Resolver for Expr {
             }
             ExprKind::This { keyword } => {
                 if class_stack.is_empty() {
-                    error(keyword.line, &("Can't use 'this' outside of a class.".into()));
+                    error(
+                        keyword.line,
+                        &("Can't use 'this' outside of a class.".into()),
+                    );
                     *had_error = true;
                     Ok(())
+                } else {
+                    resolve_local(self.0, keyword, environment)
                 }
-                 else {resolve_local(self.0, keyword, environment)}},
-            ExprKind::Unary { operator: _, right } => right.resolve(environment, function_stack, class_stack, had_error),
+            }
+            ExprKind::Unary { operator: _, right } => right.resolve(
+                environment,
+                function_stack,
+                class_stack,
+                declared_later,
+                had_error,
+            ),
             ExprKind::Variable { name } => {
-                if let Some(scope) = environment.scopes.last_mut() && scope.get(&name.lexeme).is_some_and(|&&b| !b) {
-                    Err((name.clone(), "Can't read local variable in its own initializer.".into()))
+                if let Some(scope) = environment.scopes.last_mut()
+                    && scope.get(&*name.lexeme).is_some_and(|&&b| !b)
+                {
+                    Err((
+                        name.clone(),
+                        "Can't read local variable in its own initializer.".into(),
+                    ))
+                } else if is_declared_later(name, environment, declared_later) {
+                    Err((
+                        name.clone(),
+                        format!(
+                            "Can't read local variable '{}' before its declaration.",
+                            name.lexeme
+                        )
+                        .into(),
+                    ))
                 } else {
                     let name = name.clone();
                     resolve_local(self.0, &name, environment)
@@ -272,30 +496,90 @@ impl Resolver for Expr {
     }
 }
 
-fn begin_scope(environment: &mut Environment) {
+/// `declared_later` holds, for each scope, the names it declares further down
+fn begin_scope(environment: &mut Environment, declared_later: &mut Vec<HashSet<String>>) {
     environment.scopes.push(HashMap::new());
+    declared_later.push(HashSet::new());
 }
 
-fn end_scope(environment: &mut Environment) {
+fn end_scope(environment: &mut Environment, declared_later: &mut Vec<HashSet<String>>) {
     environment.scopes.pop();
+    declared_later.pop();
+}
+
+/// drop the scopes of a resolution that stopped at an error, so the next one starts at
+/// the top level again
+pub fn discard_scopes(environment: &mut Environment) {
+    environment.scopes.clear();
+}
+
+/// record the names a block declares, so reading one before its declaration is caught
+fn note_later_declarations(statements: &[Stmt], declared_later: &mut [HashSet<String>]) {
+    let names = statements.iter().filter_map(|stmt| match stmt {
+        Stmt::Class { name, .. } | Stmt::Var { name, .. } => Some(name),
+        Stmt::Function(function) => Some(&function.name),
+        _ => None,
+    });
+    if let Some(later) = declared_later.last_mut() {
+        later.extend(names.map(|name| name.lexeme.to_string()));
+    }
+}
+
+/// whether the nearest scope that knows `name` only declares it further down
+fn is_declared_later(
+    name: &Token,
+    environment: &Environment,
+    declared_later: &[HashSet<String>],
+) -> bool {
+    for (scope, later) in environment
+        .scopes
+        .iter()
+        .rev()
+        .zip(declared_later.iter().rev())
+    {
+        if scope.contains_key(&*name.lexeme) {
+            return false;
+        }
+        if later.contains(&*name.lexeme) {
+            return true;
+        }
+    }
+    false
 }
 
 fn declare(name: &mut Token, environment: &mut Environment, had_error: &mut bool) {
+    if environment.warn_shadowing
+        && let Some((scope, enclosing)) = environment.scopes.split_last()
+        && !scope.contains_key(&*name.lexeme)
+        && enclosing
+            .iter()
+            .any(|outer| outer.contains_key(&*name.lexeme))
+    {
+        warning(
+            name.line,
+            &format!(
+                "'{}' shadows a variable in an enclosing scope.",
+                name.lexeme
+            )
+            .into(),
+        );
+    }
+
     if let Some(scope) = environment.scopes.last_mut() {
-        if scope.contains_key(&name.lexeme) {
+        if scope.contains_key(&*name.lexeme) {
             error(
                 name.line,
                 &("Already a variable with this name in this scope.".into()),
             );
             *had_error = true;
         }
-        scope.insert(name.lexeme.clone(), false);
+        scope.insert(name.lexeme.to_string(), false);
     }
 }
 
 fn define(name: &mut Token, environment: &mut Environment) {
     if let Some(scope) = environment.scopes.last_mut() {
-        scope.insert(name.lexeme.clone(), true);
+        scope.insert(name.lexeme.to_string(), true);
     }
 }
 
@@ -305,7 +589,7 @@ fn resolve_local(
     environment: &mut Environment,
 ) -> Result<(), (Token, Soo)> {
     for (i, scope) in environment.scopes.iter_mut().rev().enumerate() {
-        if scope.contains_key(&name.lexeme) {
+        if scope.contains_key(&*name.lexeme) {
             crate::interpreter::resolve(id, i, environment);
             break;
         }
@@ -320,31 +604,124 @@ fn resolve_function(
     class_stack: &mut Vec<ClassType>,
     had_error: &mut bool,
 ) -> Result<(), (Token, Soo)> {
-    begin_scope(environment);
-    for param in &mut function.params {
+    // a function body may read names its enclosing blocks declare further down, since it
+    // runs later, so only its own scopes are checked for reads before a declaration
+    let mut own_later = Vec::new();
+    begin_scope(environment, &mut own_later);
+    for (param, default) in function.params.iter_mut().zip(function.defaults.iter_mut()) {
+        if let Some(expr) = default {
+            expr.resolve(
+                environment,
+                function_stack,
+                class_stack,
+                &mut own_later,
+                had_error,
+            )?;
+        }
         declare(param, environment, had_error);
         define(param, environment);
     }
+    note_later_declarations(&function.body, &mut own_later);
     resolve_statements(
         &mut function.body,
         environment,
         function_stack,
         class_stack,
+        &mut own_later,
         had_error,
     )?;
-    end_scope(environment);
+    end_scope(environment, &mut own_later);
     Ok(())
 }
 
+/// record the calls a function makes to its own name in tail position, which the
+/// interpreter runs by looping instead of recursing
+fn mark_tail_calls(statements: &[Stmt], name: &str, environment: &mut Environment) {
+    for stmt in statements {
+        match stmt {
+            Stmt::Block { statements } => mark_tail_calls(statements, name, environment),
+            Stmt::If {
+                condition: _,
+                then_branch,
+                else_ifs,
+                else_branch,
+            } => {
+                mark_tail_calls(std::slice::from_ref(then_branch), name, environment);
+                for (_, stmt) in else_ifs {
+                    mark_tail_calls(std::slice::from_ref(stmt), name, environment);
+                }
+                if let Some(stmt) = else_branch {
+                    mark_tail_calls(std::slice::from_ref(stmt), name, environment);
+                }
+            }
+            Stmt::While { condition: _, body } => {
+                mark_tail_calls(std::slice::from_ref(body), name, environment)
+            }
+            Stmt::Return {
+                keyword: _,
+                value: Some(expr),
+            } => {
+                if let ExprKind::Call {
+                    callee,
+                    paren: _,
+                    arguments: _,
+                } = &expr.1
+                    && let ExprKind::Variable { name: callee } = &callee.1
+                    && &*callee.lexeme == name
+                {
+                    environment.tail_calls.borrow_mut().insert(expr.0);
+                }
+            }
+            // nested functions and classes have their own returns
+            _ => {}
+        }
+    }
+}
+
 pub fn resolve_statements(
     statements: &mut [Stmt],
     environment: &mut Environment,
     function_stack: &mut Vec<FunctionType>,
     class_stack: &mut Vec<ClassType>,
+    declared_later: &mut Vec<HashSet<String>>,
     had_error: &mut bool,
 ) -> Result<(), (Token, Soo)> {
     for statement in statements {
-        statement.resolve(environment, function_stack, class_stack, had_error)?;
+        statement.resolve(
+            environment,
+            function_stack,
+            class_stack,
+            declared_later,
+            had_error,
+        )?;
     }
     Ok(())
 }
+
+/// Print the scope depth each variable reference resolved to, or "global".
+pub fn dump_locals(statements: &[Stmt], environment: &Environment) {
+    struct LocalsDumper<'a>(&'a Environment);
+
+    impl Visitor for LocalsDumper<'_> {
+        fn visit_expr(&mut self, expr: &Expr) {
+            let name = match &expr.1 {
+                ExprKind::Assign { name, value: _ } | ExprKind::Variable { name } => Some(name),
+                ExprKind::Super { keyword, method: _ } | ExprKind::This { keyword } => {
+                    Some(keyword)
+                }
+                _ => None,
+            };
+
+            if let Some(name) = name {
+                match self.0.resolved_depth(expr.0) {
+                    Some(depth) => println!("[line {}] {}: depth {depth}", name.line, name.lexeme),
+                    None => println!("[line {}] {}: global", name.line, name.lexeme),
+                }
+            }
+
+            walk_expr(self, expr);
+        }
+    }
+
+    walk_stmts(&mut LocalsDumper(environment), statements);
+}
@@ -1,7 +1,8 @@
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::iter::Peekable;
 use std::mem;
+use std::rc::Rc;
 use std::str::Chars;
 
 use crate::error;
@@ -11,12 +12,14 @@ use crate::token_type::TokenType::{self, *};
 lazy_static! {
     static ref KEYWORDS: HashMap<&'static str, TokenType> = HashMap::from([
         ("and", And),
+        ("assert", Assert),
         ("class", Class),
         ("else", Else),
         ("false", False),
         ("for", For),
         ("fun", Fun),
         ("if", If),
+        ("import", Import),
         ("nil", Nil),
         ("or", Or),
         ("print", Print),
@@ -31,7 +34,17 @@ lazy_static! {
 
 pub struct Scanner<'a> {
     source: Peekable<Chars<'a>>,
+    /// keywords registered for this scanner in addition to `KEYWORDS`
+    extra_keywords: HashMap<&'static str, TokenType>,
     tokens: Vec<Token>,
+    /// lexemes seen so far, so repeated ones share a single allocation
+    interned: HashSet<Rc<str>>,
+    /// whether to keep comments and the spelling of strings, see `scan_tokens_and_comments`
+    keep_source: bool,
+    /// each `//` comment with its line, when keeping the source
+    comments: Vec<(usize, String)>,
+    /// the string being scanned as written, when keeping the source
+    spelling: String,
     text: String,
     line: usize,
 }
@@ -40,13 +53,42 @@ impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Self {
         Scanner {
             source: source.chars().peekable(),
+            extra_keywords: HashMap::new(),
             tokens: Vec::new(),
+            interned: HashSet::new(),
+            keep_source: false,
+            comments: Vec::new(),
+            spelling: String::new(),
             text: String::new(),
             line: 1,
         }
     }
 
+    /// create a scanner that also recognizes `extra` keywords, which take
+    /// precedence over the built-in ones, for trying out dialects
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_keywords(source: &'a str, extra: &[(&'static str, TokenType)]) -> Self {
+        let mut scanner = Scanner::new(source);
+        scanner.extra_keywords.extend(extra.iter().copied());
+        scanner
+    }
+
     pub fn scan_tokens(mut self) -> (Vec<Token>, bool) {
+        let had_error = self.scan();
+        (self.tokens, had_error)
+    }
+
+    /// Like `scan_tokens`, also keeping what a formatter needs to reproduce the source:
+    /// each `//` comment with its line, and strings spelled as written, quotes and
+    /// escapes included, in their lexemes.
+    pub fn scan_tokens_and_comments(mut self) -> (Vec<Token>, bool, Vec<(usize, String)>) {
+        self.keep_source = true;
+        let had_error = self.scan();
+        (self.tokens, had_error, self.comments)
+    }
+
+    /// scan the whole source, returning whether it had an error
+    fn scan(&mut self) -> bool {
         let mut had_error = false;
 
         while let Some(c) = self.get_next_token() {
@@ -60,7 +102,7 @@ impl<'a> Scanner<'a> {
         //     literal: Literal::None,
         //     line: self.line,
         // });
-        (self.tokens, had_error)
+        had_error
     }
 
     fn get_next_token(&mut self) -> Option<char> {
@@ -73,6 +115,7 @@ impl<'a> Scanner<'a> {
             ')' => self.add_token(RightParen),
             '{' => self.add_token(LeftBrace),
             '}' => self.add_token(RightBrace),
+            ':' => self.add_token(Colon),
             ',' => self.add_token(Comma),
             '.' => self.add_token(Dot),
             '-' => self.add_token(Minus),
@@ -95,14 +138,22 @@ impl<'a> Scanner<'a> {
                 let matched = self.match_next('=');
                 self.add_token(if matched { GreaterEqual } else { Greater })
             }
+            '?' if self.match_next('.') => self.add_token(QuestionDot),
+            // C-style aliases for the `and` and `or` keywords
+            '&' | '|' if self.match_next(c) => self.add_token(if c == '&' { And } else { Or }),
             '/' => {
                 if self.match_next('/') {
                     while let Some(&char) = self.source.peek() {
                         if char == '\n' {
                             break;
                         }
+                        self.text.push(char);
                         self.source.next();
-                        self.text.clear();
+                    }
+                    let comment = mem::take(&mut self.text);
+                    if self.keep_source {
+                        self.comments
+                            .push((self.line, comment.trim_end().to_owned()));
                     }
                 } else {
                     self.add_token(Slash);
@@ -115,7 +166,7 @@ impl<'a> Scanner<'a> {
                 self.line += 1;
                 self.text.pop();
             }
-            '"' => self.scan_string(),
+            '"' => return self.scan_string(),
             _ => {
                 if self.is_digit(c) {
                     self.scan_number();
@@ -131,7 +182,21 @@ impl<'a> Scanner<'a> {
         false
     }
 
-    fn scan_string(&mut self) {
+    /// scan a string, returning whether it had an error
+    fn scan_string(&mut self) -> bool {
+        if self.keep_source {
+            self.spelling.push('"');
+        }
+
+        // check for the remaining two quotes of a triple-quoted string
+        let mut cloned = self.source.clone();
+        if cloned.next() == Some('"') && cloned.next() == Some('"') {
+            self.next_in_string();
+            self.next_in_string();
+            return self.scan_triple_quoted_string();
+        }
+
+        let mut had_error = false;
         while let Some(&c) = self.source.peek() {
             if c == '"' {
                 break;
@@ -139,20 +204,102 @@ impl<'a> Scanner<'a> {
             if c == '\n' {
                 self.line += 1;
             }
+            self.next_in_string();
+            if c == '\\' && self.source.peek() == Some(&'x') {
+                self.next_in_string();
+                had_error |= self.scan_hex_escape();
+                continue;
+            }
             self.text.push(c);
-            self.source.next();
         }
 
         if self.source.peek().is_none() {
             error(self.line, &("Unterminated string.".into()));
-            return;
+            return true;
         }
 
         // closing "
-        self.source.next();
+        self.next_in_string();
 
         self.text.remove(0);
         self.add_token(StringToken);
+        had_error
+    }
+
+    /// push the character named by the two hex digits after `\x`, as a code point
+    /// so that `\xe9` is "é" rather than a lone byte
+    fn scan_hex_escape(&mut self) -> bool {
+        let mut digits = String::new();
+        while digits.len() < 2
+            && let Some(&c) = self.source.peek()
+            && c.is_ascii_hexdigit()
+        {
+            digits.push(c);
+            self.next_in_string();
+        }
+
+        match u8::from_str_radix(&digits, 16) {
+            Ok(byte) if digits.len() == 2 => {
+                self.text.push(char::from(byte));
+                false
+            }
+            _ => {
+                error(
+                    self.line,
+                    &("Invalid escape sequence, '\\x' expects two hex digits.".into()),
+                );
+                true
+            }
+        }
+    }
+
+    /// scan a string after its opening `"""`, returning whether it had an error
+    fn scan_triple_quoted_string(&mut self) -> bool {
+        // drop the opening quote, which has already been added to the text
+        self.text.clear();
+
+        let mut had_error = false;
+        let mut quotes = 0;
+        while let Some(c) = self.next_in_string() {
+            if c == '"' {
+                quotes += 1;
+                if quotes == 3 {
+                    self.add_token(StringToken);
+                    return had_error;
+                }
+                continue;
+            }
+
+            // quotes that did not close the string are part of its contents
+            for _ in 0..quotes {
+                self.text.push('"');
+            }
+            quotes = 0;
+
+            if c == '\n' {
+                self.line += 1;
+            }
+            if c == '\\' && self.source.peek() == Some(&'x') {
+                self.next_in_string();
+                had_error |= self.scan_hex_escape();
+                continue;
+            }
+            self.text.push(c);
+        }
+
+        error(self.line, &("Unterminated string.".into()));
+        true
+    }
+
+    /// consume a character of a string, keeping it in the spelling when keeping the source
+    fn next_in_string(&mut self) -> Option<char> {
+        let c = self.source.next();
+        if self.keep_source
+            && let Some(c) = c
+        {
+            self.spelling.push(c);
+        }
+        c
     }
 
     fn is_digit(&self, c: char) -> bool {
@@ -191,12 +338,13 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    /// any Unicode letter may start an identifier, keywords are all ASCII
     fn is_alpha(&self, c: char) -> bool {
-        ('a' <= c && c <= 'z') || ('A' <= c && c <= 'Z') || c == '_'
+        c.is_alphabetic() || c == '_'
     }
 
     fn is_alpha_num(&self, c: char) -> bool {
-        self.is_alpha(c) || self.is_digit(c)
+        self.is_alpha(c) || c.is_alphanumeric()
     }
 
     fn scan_identifier(&mut self) {
@@ -208,7 +356,11 @@ impl<'a> Scanner<'a> {
             self.source.next();
         }
 
-        let typ = *KEYWORDS.get(&self.text as &str).unwrap_or(&Identifier);
+        let typ = *self
+            .extra_keywords
+            .get(&self.text as &str)
+            .or_else(|| KEYWORDS.get(&self.text as &str))
+            .unwrap_or(&Identifier);
 
         self.add_token(typ);
     }
@@ -227,17 +379,37 @@ impl<'a> Scanner<'a> {
         true
     }
 
+    fn intern(&mut self, text: &str) -> Rc<str> {
+        if let Some(interned) = self.interned.get(text) {
+            return Rc::clone(interned);
+        }
+
+        let interned: Rc<str> = Rc::from(text);
+        self.interned.insert(Rc::clone(&interned));
+        interned
+    }
+
     fn add_token(&mut self, typ: TokenType) {
-        let mut lexeme = String::new();
-        mem::swap(&mut self.text, &mut lexeme);
+        // the text buffer is kept, so scanning a token only allocates for a new lexeme
+        let text = mem::take(&mut self.text);
+        let lexeme = self.intern(&text);
 
         // parse literals
         let literal: Literal = match typ {
-            Identifier => Literal::IdentifierLiteral(lexeme.clone()),
-            StringToken => Literal::StringLiteral(lexeme.clone()),
-            Number => Literal::F64(lexeme.parse().unwrap()),
+            Identifier => Literal::IdentifierLiteral(Rc::clone(&lexeme)),
+            StringToken => Literal::StringLiteral(Rc::clone(&lexeme)),
+            Number => Literal::F64(text.parse().unwrap()),
             _ => Literal::None,
         };
+        self.text = text;
+        self.text.clear();
+
+        let lexeme = if typ == StringToken && self.keep_source {
+            let spelling = mem::take(&mut self.spelling);
+            self.intern(&spelling)
+        } else {
+            lexeme
+        };
 
         self.tokens.push(Token {
             typ,
@@ -247,3 +419,91 @@ impl<'a> Scanner<'a> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(tokens: &[Token]) -> Vec<TokenType> {
+        tokens.iter().map(|token| token.typ).collect()
+    }
+
+    fn strings(tokens: &[Token]) -> Vec<&str> {
+        tokens
+            .iter()
+            .filter_map(|token| match &token.literal {
+                Literal::StringLiteral(s) => Some(&**s),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn triple_quoted_strings_span_lines() {
+        let (tokens, had_error) = Scanner::new("\"\"\"one\n  \"two\"\n\\x41\"\"\" x").scan_tokens();
+        assert!(!had_error);
+        assert_eq!(strings(&tokens), ["one\n  \"two\"\nA"]);
+        // the identifier after the string is on its last line
+        assert_eq!(tokens[1].line, 3);
+    }
+
+    #[test]
+    fn unterminated_triple_quoted_string_is_an_error() {
+        let (_, had_error) = Scanner::new("print 1;\n\"\"\"never closed\n").scan_tokens();
+        assert!(had_error);
+    }
+
+    #[test]
+    fn repeated_identifiers_share_one_allocation() {
+        let (tokens, _) = Scanner::new("count = count + other;").scan_tokens();
+        let names: Vec<&Rc<str>> = tokens
+            .iter()
+            .filter_map(|token| match &token.literal {
+                Literal::IdentifierLiteral(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(names.len(), 3);
+        assert_eq!(&**names[0], "count");
+        assert!(Rc::ptr_eq(names[0], names[1]));
+        assert!(!Rc::ptr_eq(names[0], names[2]));
+    }
+
+    #[test]
+    fn repeated_strings_and_lexemes_share_one_allocation() {
+        let (tokens, _) = Scanner::new("print \"hi\"; print \"hi\"; print \"bye\";").scan_tokens();
+        let values: Vec<&Rc<str>> = tokens
+            .iter()
+            .filter_map(|token| match &token.literal {
+                Literal::StringLiteral(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(values.len(), 3);
+        assert!(Rc::ptr_eq(values[0], values[1]));
+        assert!(!Rc::ptr_eq(values[0], values[2]));
+        // keywords and punctuation are interned like any other lexeme
+        assert!(Rc::ptr_eq(&tokens[0].lexeme, &tokens[3].lexeme));
+        assert!(Rc::ptr_eq(&tokens[2].lexeme, &tokens[5].lexeme));
+    }
+
+    #[test]
+    fn extra_keywords_are_recognized() {
+        let (tokens, had_error) =
+            Scanner::with_keywords("unless (x) y;", &[("unless", If)]).scan_tokens();
+        assert!(!had_error);
+        assert_eq!(
+            types(&tokens),
+            [If, LeftParen, Identifier, RightParen, Identifier, Semicolon]
+        );
+        assert_eq!(&*tokens[0].lexeme, "unless");
+    }
+
+    #[test]
+    fn extra_keywords_are_per_scanner() {
+        let (tokens, _) = Scanner::new("unless").scan_tokens();
+        assert_eq!(types(&tokens), [Identifier]);
+    }
+}
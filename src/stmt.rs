@@ -3,18 +3,36 @@ use crate::{expr::Expr, token::Token};
 #[derive(Clone, Debug)]
 pub struct Function {
     pub name: Token,
+    /// unique across every parse, so copies of a declaration can be recognized
+    pub id: usize,
     pub params: Vec<Token>,
+    /// default value for each parameter, only trailing parameters may have one
+    pub defaults: Vec<Option<Expr>>,
     pub body: Vec<Stmt>,
 }
 
+/// a `var` in a class body, set on each new instance before `init` runs
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: Token,
+    pub initializer: Option<Box<Expr>>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Stmt {
+    Assert {
+        keyword: Token,
+        condition: Box<Expr>,
+        /// the condition as written, for the failure message
+        source: String,
+    },
     Block {
         statements: Vec<Stmt>,
     },
     Class {
         name: Token,
         superclass: Option<Box<Expr>>,
+        fields: Vec<Field>,
         methods: Vec<Function>,
     },
     Expression {
@@ -24,8 +42,15 @@ pub enum Stmt {
     If {
         condition: Box<Expr>,
         then_branch: Box<Stmt>,
+        /// `else if` arms in order, kept flat so long chains don't nest
+        else_ifs: Vec<(Expr, Stmt)>,
         else_branch: Option<Box<Stmt>>,
     },
+    /// `import "path";` or `import "path" as alias;`, where the path is the string token
+    Import {
+        path: Token,
+        alias: Option<Token>,
+    },
     Print {
         expression: Box<Expr>,
     },
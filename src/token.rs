@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::callable::Callable;
 use crate::instance::Instance;
 use crate::token_type::TokenType;
@@ -7,16 +9,98 @@ pub enum Literal {
     BoolLiteral(bool),
     CallableLiteral(Callable),
     F64(f64),
-    IdentifierLiteral(String),
+    /// shared by every occurrence of the identifier within a scan
+    IdentifierLiteral(Rc<str>),
     InstanceLiteral(Instance),
-    StringLiteral(String),
+    /// shared by every occurrence of the string within a scan
+    StringLiteral(Rc<str>),
     None,
 }
 
 #[derive(Clone, Debug)]
 pub struct Token {
     pub typ: TokenType,
-    pub lexeme: String,
+    pub lexeme: Rc<str>,
     pub literal: Literal,
     pub line: usize,
 }
+
+impl From<bool> for Literal {
+    fn from(b: bool) -> Self {
+        Literal::BoolLiteral(b)
+    }
+}
+
+impl From<f64> for Literal {
+    fn from(f: f64) -> Self {
+        Literal::F64(f)
+    }
+}
+
+impl From<String> for Literal {
+    fn from(s: String) -> Self {
+        Literal::StringLiteral(s.into())
+    }
+}
+
+/// the conversions back give the literal itself as the error when it holds another type
+impl TryFrom<Literal> for bool {
+    type Error = Literal;
+
+    fn try_from(literal: Literal) -> Result<Self, Self::Error> {
+        match literal {
+            Literal::BoolLiteral(b) => Ok(b),
+            _ => Err(literal),
+        }
+    }
+}
+
+impl TryFrom<Literal> for f64 {
+    type Error = Literal;
+
+    fn try_from(literal: Literal) -> Result<Self, Self::Error> {
+        match literal {
+            Literal::F64(f) => Ok(f),
+            _ => Err(literal),
+        }
+    }
+}
+
+impl TryFrom<Literal> for String {
+    type Error = Literal;
+
+    fn try_from(literal: Literal) -> Result<Self, Self::Error> {
+        match literal {
+            Literal::StringLiteral(s) => Ok(s.to_string()),
+            _ => Err(literal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_and_from_literals() {
+        assert_eq!(bool::try_from(Literal::from(true)).ok(), Some(true));
+        assert_eq!(f64::try_from(Literal::from(1.5)).ok(), Some(1.5));
+        assert_eq!(
+            String::try_from(Literal::from("lox".to_owned())).ok(),
+            Some("lox".to_owned())
+        );
+    }
+
+    #[test]
+    fn failed_conversions_give_back_the_literal() {
+        assert!(matches!(
+            f64::try_from(Literal::from(true)),
+            Err(Literal::BoolLiteral(true))
+        ));
+        assert!(matches!(bool::try_from(Literal::None), Err(Literal::None)));
+        assert!(matches!(
+            String::try_from(Literal::from(2.0)),
+            Err(Literal::F64(f)) if f == 2.0
+        ));
+    }
+}
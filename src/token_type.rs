@@ -5,6 +5,7 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -22,6 +23,7 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionDot,
 
     // literals
     Identifier,
@@ -30,12 +32,14 @@ pub enum TokenType {
 
     // keywords
     And,
+    Assert,
     Class,
     Else,
     False,
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
@@ -1,4 +1,11 @@
-use std::fmt;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    env, fmt,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
 
 /// Represents either a static or owned string
 pub enum Soo {
@@ -27,16 +34,208 @@ impl std::convert::From<String> for Soo {
     }
 }
 
-pub struct ExprId(usize);
+/// ids are unique across every parse in the process, so code parsed later
+/// (REPL lines, `eval`) never overwrites the resolved locals of earlier code
+static NEXT_EXPR_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct ExprId;
 
 impl ExprId {
     pub fn new() -> Self {
-        ExprId(0)
+        ExprId
     }
 
     pub fn next(&mut self) -> usize {
-        let id = self.0;
-        self.0 += 1;
-        id
+        NEXT_EXPR_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Red,
+    Yellow,
+    Cyan,
+}
+
+/// color diagnostics only when printing to a terminal, unless disabled by
+/// `--no-color` or a non-empty `NO_COLOR` variable
+pub fn init_color(no_color: bool) {
+    let disabled_by_env = matches!(env::var_os("NO_COLOR"), Some(value) if !value.is_empty());
+    COLOR.store(
+        !no_color && !disabled_by_env && stdout_is_terminal(),
+        Ordering::Relaxed,
+    );
+}
+
+#[cfg(unix)]
+fn stdout_is_terminal() -> bool {
+    use std::os::raw::c_int;
+
+    extern "C" {
+        fn isatty(fd: c_int) -> c_int;
+    }
+    // SAFETY: `isatty` only inspects the descriptor, and 1 is always stdout
+    unsafe { isatty(1) == 1 }
+}
+
+#[cfg(not(unix))]
+fn stdout_is_terminal() -> bool {
+    false
+}
+
+/// wrap text in an ANSI color code when colored output is enabled
+pub fn paint(text: &str, color: Color) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return text.to_owned();
+    }
+
+    let code = match color {
+        Color::Red => 31,
+        Color::Yellow => 33,
+        Color::Cyan => 36,
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+/// diagnostics whose text can be replaced by installing a message catalog
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// the expected count and the actual count of arguments
+    ArgumentCount,
+    ExpectedExpression,
+    NotCallable,
+    /// the property name
+    UndefinedProperty,
+    /// the variable name
+    UndefinedVariable,
+}
+
+/// Gives the template for a message, or `None` to fall back to English.
+///
+/// Each `{}` in a template is replaced by the message's next argument.
+pub type Catalog = fn(Message) -> Option<&'static str>;
+
+impl Message {
+    /// the message a catalog file calls `name`, which is its variant's name
+    fn from_name(name: &str) -> Option<Message> {
+        match name {
+            "ArgumentCount" => Some(Message::ArgumentCount),
+            "ExpectedExpression" => Some(Message::ExpectedExpression),
+            "NotCallable" => Some(Message::NotCallable),
+            "UndefinedProperty" => Some(Message::UndefinedProperty),
+            "UndefinedVariable" => Some(Message::UndefinedVariable),
+            _ => None,
+        }
+    }
+}
+
+fn no_translations(_: Message) -> Option<&'static str> {
+    None
+}
+
+thread_local! {
+    static CATALOG: Cell<Catalog> = Cell::new(no_translations);
+    /// the templates of the catalog file loaded on this thread, see `load_catalog`
+    static LOADED: RefCell<HashMap<Message, &'static str>> = RefCell::new(HashMap::new());
+}
+
+/// use `catalog` for every message reported after this on the current thread
+pub fn set_catalog(catalog: Catalog) {
+    CATALOG.with(|current| current.set(catalog));
+}
+
+fn loaded_catalog(message: Message) -> Option<&'static str> {
+    LOADED.with(|loaded| loaded.borrow().get(&message).copied())
+}
+
+/// Install the catalog in the text of a catalog file on the current thread.
+///
+/// Each line is `Name = template`, such as `UndefinedVariable = Variable '{}' non définie.`,
+/// and blank lines and lines starting with `#` are skipped.
+pub fn load_catalog(text: &str) -> Result<(), String> {
+    let mut templates = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, template) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Expected 'Name = template' on line {}.", i + 1))?;
+        let message = Message::from_name(name.trim())
+            .ok_or_else(|| format!("Unknown message '{}' on line {}.", name.trim(), i + 1))?;
+        // a catalog is loaded once, so its templates can live for the rest of the program
+        let template: &'static str = Box::leak(template.trim().to_owned().into_boxed_str());
+        templates.insert(message, template);
+    }
+
+    LOADED.with(|loaded| *loaded.borrow_mut() = templates);
+    set_catalog(loaded_catalog);
+    Ok(())
+}
+
+fn english(message: Message) -> &'static str {
+    match message {
+        Message::ArgumentCount => "Expected {} arguments but got {}.",
+        Message::ExpectedExpression => "Expected expression.",
+        Message::NotCallable => "Can only call functions and classes.",
+        Message::UndefinedProperty => "Undefined property '{}'.",
+        Message::UndefinedVariable => "Undefined variable '{}'.",
+    }
+}
+
+/// the text of a message from the installed catalog, filled in with `args`
+pub fn localize(message: Message, args: &[&dyn fmt::Display]) -> Soo {
+    let template = CATALOG.with(Cell::get)(message).unwrap_or_else(|| english(message));
+    if args.is_empty() {
+        return Soo::Static(template);
+    }
+
+    let mut text = String::new();
+    for (i, piece) in template.split("{}").enumerate() {
+        if i > 0 {
+            match args.get(i - 1) {
+                Some(arg) => text.push_str(&arg.to_string()),
+                _ => text.push_str("{}"),
+            }
+        }
+        text.push_str(piece);
+    }
+    Soo::Owned(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Environment,
+        token::{Literal, Token},
+        token_type::TokenType,
+    };
+
+    fn french(message: Message) -> Option<&'static str> {
+        match message {
+            Message::UndefinedVariable => Some("Variable '{}' non définie."),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn catalog_replaces_undefined_variable() {
+        set_catalog(french);
+        let name = Token {
+            typ: TokenType::Identifier,
+            lexeme: "x".into(),
+            literal: Literal::None,
+            line: 1,
+        };
+        let error = Environment::new().assign(&name, Literal::None).err();
+        let message = error.map(|(_, message)| message.to_string());
+        assert_eq!(message.as_deref(), Some("Variable 'x' non définie."));
+
+        // messages the catalog leaves out stay in English
+        let message = localize(Message::UndefinedProperty, &[&"y"]).to_string();
+        assert_eq!(message, "Undefined property 'y'.");
+        set_catalog(no_translations);
     }
 }
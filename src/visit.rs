@@ -0,0 +1,184 @@
+use crate::{
+    expr::{Expr, ExprKind},
+    stmt::{Function, Stmt},
+};
+
+/// Read-only traversal of the AST for tooling such as linters and formatters.
+///
+/// The default methods walk every child node, so implementors only need to
+/// override the nodes they care about and call `walk_stmt`/`walk_expr` to keep
+/// descending.
+pub trait Visitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_stmts<V: Visitor + ?Sized>(visitor: &mut V, statements: &[Stmt]) {
+    for stmt in statements {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Assert {
+            keyword: _,
+            condition,
+            source: _,
+        } => visitor.visit_expr(condition),
+        Stmt::Block { statements } => walk_stmts(visitor, statements),
+        Stmt::Class {
+            name: _,
+            superclass,
+            fields,
+            methods,
+        } => {
+            if let Some(expr) = superclass {
+                visitor.visit_expr(expr);
+            }
+            for expr in fields.iter().filter_map(|field| field.initializer.as_ref()) {
+                visitor.visit_expr(expr);
+            }
+            for method in methods {
+                walk_function(visitor, method);
+            }
+        }
+        Stmt::Expression { expression } => visitor.visit_expr(expression),
+        Stmt::Function(function) => walk_function(visitor, function),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_ifs,
+            else_branch,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(then_branch);
+            for (condition, stmt) in else_ifs {
+                visitor.visit_expr(condition);
+                visitor.visit_stmt(stmt);
+            }
+            if let Some(stmt) = else_branch {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        Stmt::Import { .. } => {}
+        Stmt::Print { expression } => visitor.visit_expr(expression),
+        Stmt::Return { keyword: _, value } => {
+            if let Some(expr) = value {
+                visitor.visit_expr(expr);
+            }
+        }
+        Stmt::Var {
+            name: _,
+            initializer,
+        } => {
+            if let Some(expr) = initializer {
+                visitor.visit_expr(expr);
+            }
+        }
+        Stmt::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, function: &Function) {
+    for expr in function.defaults.iter().flatten() {
+        visitor.visit_expr(expr);
+    }
+    walk_stmts(visitor, &function.body);
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match &expr.1 {
+        ExprKind::Assign { name: _, value } => visitor.visit_expr(value),
+        ExprKind::Binary {
+            left,
+            operator: _,
+            right,
+            operand_lines: _,
+        }
+        | ExprKind::Logical {
+            left,
+            operator: _,
+            right,
+        } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::BlockExpr { statements, value } => {
+            walk_stmts(visitor, statements);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Call {
+            callee,
+            paren: _,
+            arguments,
+        } => {
+            visitor.visit_expr(callee);
+            for argument in arguments {
+                visitor.visit_expr(argument);
+            }
+        }
+        ExprKind::Get { object, name: _ } | ExprKind::OptionalGet { object, name: _ } => {
+            visitor.visit_expr(object)
+        }
+        ExprKind::Grouping { expression } => visitor.visit_expr(expression),
+        ExprKind::Set {
+            object,
+            name: _,
+            value,
+        } => {
+            visitor.visit_expr(object);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Unary { operator: _, right } => visitor.visit_expr(right),
+        ExprKind::LiteralExpr { value: _ }
+        | ExprKind::Super {
+            keyword: _,
+            method: _,
+        }
+        | ExprKind::This { keyword: _ }
+        | ExprKind::Variable { name: _ } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, scanner::Scanner};
+
+    #[derive(Default)]
+    struct CallCounter {
+        calls: usize,
+    }
+
+    impl Visitor for CallCounter {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let ExprKind::Call { .. } = expr.1 {
+                self.calls += 1;
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn counts_calls() {
+        let source = "fun f(x) { return g(x) + g(h(x)); }\n\
+                      class A { m() { print this.n(f(1)); } }\n\
+                      if (f(2)) print 1; else if (f(3)) print 2;\n";
+        let (tokens, had_error) = Scanner::new(source).scan_tokens();
+        assert!(!had_error);
+        let statements = parser::parse(tokens, None).unwrap_or_else(|_| panic!("parse error"));
+
+        let mut counter = CallCounter::default();
+        walk_stmts(&mut counter, &statements);
+        assert_eq!(counter.calls, 7);
+    }
+}
@@ -0,0 +1,885 @@
+//! Runs the `rlox` binary on small programs and checks what it reports.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{self, Command, Output, Stdio};
+
+/// run `rlox` with `args` on `source`, written to a script file named after the test
+fn run_script(name: &str, args: &[&str], source: &str) -> Output {
+    let path = env::temp_dir().join(format!("rlox-{}-{name}.lox", process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+/// run the REPL with `input` typed into it
+fn run_repl(input: &str) -> Output {
+    run_with_input(Command::new(env!("CARGO_BIN_EXE_rlox")), input)
+}
+
+/// run `source` as a script with `input` on its stdin
+fn run_script_with_input(name: &str, source: &str, input: &str) -> Output {
+    let path = env::temp_dir().join(format!("rlox-{}-{name}.lox", process::id()));
+    fs::write(&path, source).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rlox"));
+    command.arg("--no-color").arg(&path);
+    let output = run_with_input(command, input);
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+fn run_with_input(mut command: Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn deep_recursion_is_a_runtime_error() {
+    let output = run_script(
+        "deep_recursion",
+        &[],
+        "fun g(n) { return 1 + g(n); }\ng(0);\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stdout(&output).starts_with("Stack overflow."));
+}
+
+#[test]
+fn recursive_field_initializer_is_a_runtime_error() {
+    let output = run_script(
+        "recursive_field",
+        &[],
+        "class N { var child = N(); }\nN();\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stdout(&output).starts_with("Stack overflow."));
+}
+
+#[test]
+fn long_else_if_chain_does_not_overflow() {
+    let mut source = String::from("var n = 499;\nif (n == 0) print 0;");
+    for arm in 1..500 {
+        source.push_str(&format!(" else if (n == {arm}) print {arm};"));
+    }
+    source.push_str(" else print \"none\";\n");
+
+    let output = run_script("else_if", &[], &source);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "499\n");
+
+    let output = run_script("else_if_check", &["--check"], &source);
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = run_script("else_if_fmt", &["--fmt"], &source);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains(" else if (n == 499) print 499;"));
+}
+
+#[test]
+fn string_repetition() {
+    let output = run_script(
+        "repeat",
+        &[],
+        "print \"ab\" * 3;\nprint 2 * \"x\";\nprint \"ab\" * 0;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "ababab\nxx\n\n");
+}
+
+#[test]
+fn string_repetition_count_errors() {
+    for (name, count) in [
+        ("negative", "-1"),
+        ("fraction", "1.5"),
+        ("huge", "100000000000000000000"),
+    ] {
+        let output = run_script(
+            &format!("repeat_{name}"),
+            &[],
+            &format!("print \"a\" * {count};\n"),
+        );
+        assert_eq!(output.status.code(), Some(70), "count {count}");
+    }
+}
+
+#[test]
+fn import_as_copies_what_the_file_defines() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("scripts/import_as.lox")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "9\ntrue\nmine\n16\n2\ntrue\n1\n0\n");
+}
+
+#[test]
+fn optional_chain_short_circuits() {
+    let output = run_script(
+        "optional_short_circuit",
+        &[],
+        "fun side() { print \"side\"; }\nvar a = nil;\nprint a?.f(side());\nprint a?.b.c;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "nil\nnil\n");
+}
+
+#[test]
+fn get_after_optional_get_still_checks_its_object() {
+    let output = run_script(
+        "optional_then_get",
+        &[],
+        "class A {}\nvar a = A();\na.b = nil;\nprint a?.b.c;\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stdout(&output).starts_with("Only instances have properties."));
+}
+
+#[test]
+fn fmt_keeps_comments_and_for_loops() {
+    let source =
+        "var a = 1; // one\n\n// loop\nfor (var i = 0; i < a; i = i + 1) print \"\\x22\" + i;\n";
+    let output = run_script("fmt_comments", &["--fmt"], source);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), source);
+}
+
+#[test]
+fn repl_type_command() {
+    let output = run_repl(":type 1 + 1\n:type \"a\"\n");
+    assert_eq!(
+        stdout(&output),
+        "rlox[0]> number\nrlox[0]> string\nrlox[0]> "
+    );
+}
+
+#[test]
+fn repl_type_command_only_takes_an_expression() {
+    let output = run_repl(":type var leaked = 1;\nprint leaked;\n");
+    let out = stdout(&output);
+    assert!(out.contains("Unable to compile the expression passed to ':type'."));
+    assert!(out.contains("Undefined variable 'leaked'."));
+}
+
+#[test]
+fn repl_type_command_needs_a_word_boundary() {
+    let output = run_repl(":typex\n");
+    assert_eq!(
+        stdout(&output),
+        "rlox[0]> [line 1] Error at ':': Expected expression.\nParse errors encountered.\nrlox[0]> "
+    );
+}
+
+#[test]
+fn radix_conversions_reject_magnitudes_past_u64() {
+    let output = run_script(
+        "radix_largest",
+        &[],
+        "print hex(18446744073709549568);\nprint bin(-1);\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "0xfffffffffffff800\n-0b1\n");
+
+    for name in ["bin", "hex", "oct"] {
+        let output = run_script(
+            &format!("radix_{name}_too_large"),
+            &[],
+            &format!("print {name}(-18446744073709551616);\n"),
+        );
+        assert_eq!(output.status.code(), Some(70), "{name}");
+        assert!(stdout(&output).contains("smaller in magnitude than 2^64"));
+    }
+}
+
+#[test]
+fn cloned_instances_are_distinct() {
+    let output = run_script(
+        "clone_identity",
+        &[],
+        "class A {}\nvar a = A();\nvar b = a;\nprint a == b;\nprint clone(a) == a;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "true\nfalse\n");
+}
+
+#[test]
+fn no_color_gives_plain_diagnostics() {
+    let output = run_script("no_color", &["--no-color"], "print 1;\nprint -\"a\";\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "1\nOperand must be a number.\n[line 2]\n");
+}
+
+#[test]
+fn unterminated_triple_quoted_string_stops_the_program() {
+    let output = run_script("unterminated_triple", &[], "print 1;\n\"\"\"never closed\n");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(!stdout(&output).starts_with("1\n"));
+}
+
+#[test]
+fn callable_equality() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("scripts/callable_equality.lox")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "true\nfalse\ntrue\nfalse\ntrue\nfalse\ntrue\nfalse\nfalse\ntrue\nfalse\n"
+    );
+}
+
+#[test]
+fn functions_from_different_files_differ() {
+    let dir = env::temp_dir().join(format!("rlox-{}-imports", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.lox"), "fun helper() {}\n").unwrap();
+    fs::write(dir.join("b.lox"), "fun helper() {}\n").unwrap();
+    let main = dir.join("main.lox");
+    fs::write(
+        &main,
+        "import \"a.lox\" as a;\nimport \"b.lox\" as b;\nprint a.helper == b.helper;\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg(&main)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&output), "false\n");
+}
+
+#[test]
+fn block_expressions_accept_assert_statements() {
+    let output = run_script(
+        "block_assert",
+        &[],
+        "var x = true;\nprint { assert x; 1 };\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1\n");
+}
+
+#[test]
+fn runtime_type_errors_report_their_line() {
+    let cases = [
+        ("print 1;\nprint \"a\" - 1;\n", "[line 2]"),
+        ("print 1;\nprint 2;\nprint nil.x;\n", "[line 3]"),
+        ("print 1;\n\n\n1();\n", "[line 4]"),
+    ];
+    for (source, line) in cases {
+        let output = run_script("error_lines", &["--no-color"], source);
+        assert_eq!(output.status.code(), Some(70));
+        assert!(
+            stdout(&output).ends_with(&format!("{line}\n")),
+            "{source:?}"
+        );
+    }
+}
+
+#[test]
+fn stack_overflow_backtrace_is_truncated() {
+    let output = run_script("overflow", &["--no-color"], "fun g() { g(); }\ng();\n");
+    assert_eq!(output.status.code(), Some(70));
+    let out = stdout(&output);
+    assert!(out.starts_with("Stack overflow.\n[line 1]\n"));
+    assert_eq!(
+        out.lines()
+            .filter(|line| line.starts_with("  in g()"))
+            .count(),
+        20
+    );
+    assert!(out.contains("  … 2028 more\n"));
+    assert!(out.ends_with("  in g() called from [line 2]\n"));
+}
+
+#[test]
+fn short_backtrace_lists_every_frame() {
+    let output = run_script(
+        "short_backtrace",
+        &["--no-color"],
+        "fun f() { nil(); }\nfun h() { f(); }\nh();\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "Can only call functions and classes.\n[line 1]\n  in f() called from [line 2]\n  in h() called from [line 3]\n"
+    );
+}
+
+#[test]
+fn max_errors_stops_after_that_many_reported_errors() {
+    let source = "print 1 +;\nprint ;\nprint 2 +;\nprint 3;\n";
+    let error = |line| format!("[line {line}] Error at ';': Expected expression.\n");
+
+    let output = run_script("max_errors_1", &["--no-color", "--max-errors", "1"], source);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        stdout(&output),
+        error(1) + "too many errors; aborting.\nParse errors encountered.\n"
+    );
+
+    let output = run_script("max_errors_2", &["--no-color", "--max-errors", "2"], source);
+    assert_eq!(
+        stdout(&output),
+        error(1) + &error(2) + "too many errors; aborting.\nParse errors encountered.\n"
+    );
+
+    let output = run_script("max_errors_all", &["--no-color"], source);
+    assert_eq!(
+        stdout(&output),
+        error(1) + &error(2) + &error(3) + "Parse errors encountered.\n"
+    );
+}
+
+#[test]
+fn messages_flag_loads_a_catalog() {
+    let catalog = env::temp_dir().join(format!("rlox-{}-messages.txt", process::id()));
+    fs::write(
+        &catalog,
+        "# French\nUndefinedVariable = Variable '{}' non définie.\n",
+    )
+    .unwrap();
+    let catalog_arg = catalog.to_str().unwrap();
+
+    let output = run_script(
+        "messages",
+        &["--no-color", "--messages", catalog_arg],
+        "print missing;\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "Variable 'missing' non définie.\n[line 1]\n"
+    );
+
+    fs::write(&catalog, "Undefined = nope\n").unwrap();
+    let output = run_script("bad_messages", &["--messages", catalog_arg], "print 1;\n");
+    fs::remove_file(&catalog).unwrap();
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stdout(&output).starts_with("Unknown message 'Undefined' on line 1.\n"));
+}
+
+#[test]
+fn eval_gives_the_final_value_and_keeps_definitions() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("scripts/eval.lox")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\n3\n11\n12\nnil\n");
+}
+
+#[test]
+fn assert_eq_reports_both_values() {
+    let output = run_script(
+        "assert_eq",
+        &["--no-color"],
+        "assert_eq(2, 1 + 1);\nprint \"ok\";\nassert_eq(\"a\", 1);\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "ok\nAssertion failed: expected a but got 1.\n[line 3]\n"
+    );
+}
+
+#[test]
+fn time_reports_phases_on_stderr() {
+    let output = run_script("time", &["--time"], "print 1 + 1;\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "2\n");
+    let phases: Vec<String> = stderr(&output)
+        .lines()
+        .map(|line| line.split(':').next().unwrap().to_owned())
+        .collect();
+    assert_eq!(
+        phases,
+        [
+            "[time] scan",
+            "[time] parse",
+            "[time] resolve",
+            "[time] optimize",
+            "[time] interpret"
+        ]
+    );
+}
+
+#[test]
+fn named_arguments_are_reported_at_the_name() {
+    let output = run_script(
+        "named_arguments",
+        &["--no-color"],
+        "fun f(a) { return a; }\nprint f(1);\nprint f(\n  a: 2);\n",
+    );
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        stdout(&output),
+        "[line 4] Error at 'a': Named arguments are not supported.\nParse errors encountered.\n"
+    );
+}
+
+#[test]
+fn warn_shadow_flags_locals_shadowing_locals() {
+    let source = "var a = 0;\n{\n  var a = 1;\n  {\n    var a = 2;\n    print a;\n  }\n}\n";
+    let output = run_script("warn_shadow", &["--no-color", "--warn-shadow"], source);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "[line 5] Warning: 'a' shadows a variable in an enclosing scope.\n2\n"
+    );
+
+    let output = run_script("no_warn_shadow", &["--no-color"], source);
+    assert_eq!(stdout(&output), "2\n");
+}
+
+#[test]
+fn trace_prints_to_stderr_and_returns_its_argument() {
+    let output = run_script(
+        "trace",
+        &[],
+        "var x = 2;\nprint trace(x) + 1;\nprint trace(\"s\");\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "3\ns\n");
+    assert_eq!(stderr(&output), "[trace] 2\n[trace] s\n");
+}
+
+#[test]
+fn natives_check_their_arity() {
+    let output = run_script(
+        "getchar_arity",
+        &["--no-color"],
+        "print 1;\nprint getchar(\"abc\");\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "1\nExpected 2 arguments but got 1.\n[line 2]\n"
+    );
+
+    let output = run_script(
+        "getchar_extra",
+        &["--no-color"],
+        "print getchar(\"abc\", 1, 2);\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "Expected 2 arguments but got 3.\n[line 1]\n"
+    );
+}
+
+#[test]
+fn format_fills_placeholders_and_checks_their_count() {
+    let output = run_script(
+        "format",
+        &["--no-color"],
+        "print format(\"{} and {}\", 1, \"two\");\nprint format(\"none\");\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1 and two\nnone\n");
+
+    let output = run_script(
+        "format_extra",
+        &["--no-color"],
+        "print format(\"{}\", 1, 2);\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "Template has 1 placeholders but got 2 arguments.\n[line 1]\n"
+    );
+
+    let output = run_script(
+        "format_missing",
+        &["--no-color"],
+        "print format(\"{} {}\", 1);\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "Template has 2 placeholders but got 1 arguments.\n[line 1]\n"
+    );
+}
+
+#[test]
+fn abs_and_sign() {
+    let output = run_script(
+        "abs_sign",
+        &["--no-color"],
+        "print abs(-3);\nprint abs(2.5);\nprint sign(-2);\nprint sign(5);\nprint sign(0);\n\
+         print 1 / abs(-0);\nprint 1 / sign(-0);\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    // `abs(-0)` and `sign(-0)` are positive zero, so dividing by them gives inf
+    assert_eq!(stdout(&output), "3\n2.5\n-1\n1\n0\ninf\ninf\n");
+
+    for name in ["abs", "sign"] {
+        let output = run_script(name, &["--no-color"], &format!("print {name}(\"x\");\n"));
+        assert_eq!(output.status.code(), Some(70));
+        assert_eq!(
+            stdout(&output),
+            format!("Invalid function arguments, '{name}' accepts a single number.\n[line 1]\n")
+        );
+    }
+}
+
+#[test]
+fn read_number_reads_a_line_of_stdin() {
+    let source = "var n = read_number();\nprint n * 2;\n";
+    let output = run_script_with_input("read_number", source, " 21 \n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "42\n");
+
+    let output = run_script_with_input("read_number_invalid", source, "many\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "Unable to parse input as a number.\n[line 1]\n"
+    );
+
+    let output = run_script_with_input("read_number_eof", source, "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "Unexpected end of input.\n[line 1]\n");
+}
+
+#[test]
+fn coerce_lets_plus_join_strings_and_numbers() {
+    let source = "print \"n\" + 1;\nprint 2.5 + \"x\";\n";
+    let output = run_script("coerce", &["--no-color", "--coerce"], source);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "n1\n2.5x\n");
+
+    let output = run_script("no_coerce", &["--no-color"], source);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "Operands must be two numbers or two strings, but the right operand on line 1 is a number.\n[line 1]\n"
+    );
+}
+
+#[test]
+fn type_predicates() {
+    let output = run_script(
+        "predicates",
+        &["--no-color"],
+        "class A {}\n\
+         print is_bool(true); print is_bool(nil);\n\
+         print is_callable(clock); print is_callable(A); print is_callable(A());\n\
+         print is_instance(A()); print is_instance(A);\n\
+         print is_integer(2); print is_integer(2.5); print is_integer(1 / 0);\n\
+         print is_nil(nil); print is_nil(false);\n\
+         print is_number(1); print is_number(\"1\");\n\
+         print is_string(\"1\"); print is_string(1);\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "true\nfalse\ntrue\ntrue\nfalse\ntrue\nfalse\ntrue\nfalse\nfalse\ntrue\nfalse\ntrue\nfalse\ntrue\nfalse\n"
+    );
+}
+
+#[test]
+fn repr_escapes_quotes_in_strings() {
+    let output = run_script(
+        "repr",
+        &["--no-color"],
+        "print repr(\"say \\x22hi\\x22\");\nprint repr(\"\"\"a \"b\" c\"\"\");\n\
+         print repr(\"back\\\\slash\");\nprint repr(1);\nprint repr(nil);\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "\"say \\\"hi\\\"\"\n\"a \\\"b\\\" c\"\n\"back\\\\\\\\slash\"\n1\nnil\n"
+    );
+}
+
+#[test]
+fn dump_locals_prints_resolution_instead_of_running() {
+    let output = run_script(
+        "dump_locals",
+        &["--dump-locals"],
+        "var g = 1;\nfun f(a) {\n  var b = a;\n  {\n    var c = b + g;\n    print c;\n  }\n}\nf(1);\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "[line 3] a: depth 0\n[line 5] b: depth 1\n[line 5] g: global\n[line 6] c: depth 0\n[line 9] f: global\n"
+    );
+}
+
+#[test]
+fn precision_limits_significant_digits() {
+    let source = "print 1 / 3;\nprint 2 / 3;\nprint 10;\nprint 1.5;\n";
+    let output = run_script("precision", &["--precision", "3"], source);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "0.333\n0.667\n10\n1.5\n");
+
+    let output = run_script("no_precision", &[], source);
+    assert_eq!(
+        stdout(&output),
+        "0.3333333333333333\n0.6666666666666666\n10\n1.5\n"
+    );
+
+    let output = run_script("zero_precision", &["--precision", "0"], source);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stdout(&output).starts_with("'--precision' expects a positive number of digits.\n"));
+}
+
+#[test]
+fn c_style_logical_operators_short_circuit() {
+    let output = run_script(
+        "logical_aliases",
+        &["--no-color"],
+        "fun boom() { print \"evaluated\"; return true; }\n\
+         print true && false;\nprint false || true;\nprint nil || \"d\";\nprint 1 && 2;\n\
+         print false && boom();\nprint true || boom();\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "false\ntrue\nd\n2\nfalse\ntrue\n");
+
+    let output = run_script("single_ampersand", &["--no-color"], "print 1 & 2;\n");
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn repl_prompt_counts_globals() {
+    let output = run_repl("var a = 1;\nfun f() {}\nvar a = 2;\nprint a;\n");
+    assert_eq!(
+        stdout(&output),
+        "rlox[0]> rlox[1]> rlox[2]> rlox[2]> 2\nrlox[2]> "
+    );
+}
+
+#[test]
+fn mod_is_euclidean() {
+    let output = run_script(
+        "mod",
+        &["--no-color"],
+        "print mod(7, 3);\nprint mod(-7, 3);\nprint mod(7, -3);\nprint mod(7.5, 2);\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1\n2\n1\n1.5\n");
+
+    let output = run_script("mod_zero", &["--no-color"], "print mod(1, 0);\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "Modulo by zero.\n[line 1]\n");
+
+    let output = run_script("mod_string", &["--no-color"], "print mod(\"a\", 1);\n");
+    assert_eq!(
+        stdout(&output),
+        "Invalid function arguments, 'mod' accepts two numbers.\n[line 1]\n"
+    );
+}
+
+#[test]
+fn binary_errors_name_the_operand_and_its_line() {
+    let output = run_script(
+        "multi_line_minus",
+        &["--no-color"],
+        "var total = 1\n  -\n  \"two\";\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "Operands must be numbers, but the right operand on line 3 is a string.\n[line 2]\n"
+    );
+
+    let output = run_script(
+        "multi_line_plus",
+        &["--no-color"],
+        "print 1 +\n  \"a\"\n  - 2;\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "Operands must be two numbers or two strings, but the right operand on line 2 is a string.\n[line 1]\n"
+    );
+}
+
+#[test]
+fn print_uses_to_string_when_a_class_defines_it() {
+    let output = run_script(
+        "to_string",
+        &["--no-color"],
+        "class Point {\n  init(x, y) { this.x = x; this.y = y; }\n  \
+         toString() { return format(\"({}, {})\", this.x, this.y); }\n}\n\
+         class Plain {}\nprint Point(1, 2);\nprint Plain();\n\
+         class Bad { toString() { return 1; } }\nprint Bad();\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert!(
+        stdout(&output).starts_with("(1, 2)\nPlain instance\n'toString' must return a string.\n")
+    );
+}
+
+#[test]
+fn super_reports_missing_methods() {
+    let output = run_script(
+        "super_missing",
+        &["--no-color"],
+        "class A { greet() { return \"A\"; } }\nclass B < A {\n  \
+         greet() { return \"B\" + super.greet(); }\n  missing() { return super.nonexistent; }\n}\n\
+         print B().greet();\nprint B().missing();\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "BA\nUndefined method 'nonexistent' in superclass 'A'.\n[line 4]\n  in missing() called from [line 7]\n"
+    );
+}
+
+#[test]
+fn cyclic_instances_print_and_compare() {
+    let output = run_script(
+        "cycles",
+        &["--no-color"],
+        "class Node {}\nvar a = Node();\na.self = a;\nprint a;\nprint repr(a);\nprint a == a;\n\
+         var b = Node();\nb.next = a;\na.next = b;\nprint repr(b);\nprint a == b;\n\
+         print b.next.next == b;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "Node instance\nNode instance { self: ... }\ntrue\n\
+         Node instance { next: Node instance { next: ..., self: ... } }\nfalse\ntrue\n"
+    );
+}
+
+#[test]
+fn fmt_indent_sets_the_indentation() {
+    let source = "fun f() { if (true) { print 1; } }\n";
+    let output = run_script("indent_default", &["--fmt"], source);
+    assert_eq!(
+        stdout(&output),
+        "fun f() {\n  if (true) {\n    print 1;\n  }\n}\n"
+    );
+
+    let output = run_script("indent_2", &["--fmt", "--indent", "2"], source);
+    assert_eq!(
+        stdout(&output),
+        "fun f() {\n  if (true) {\n    print 1;\n  }\n}\n"
+    );
+
+    let output = run_script("indent_4", &["--fmt", "--indent", "4"], source);
+    assert_eq!(
+        stdout(&output),
+        "fun f() {\n    if (true) {\n        print 1;\n    }\n}\n"
+    );
+
+    let output = run_script("indent_tab", &["--fmt", "--indent", "tab"], source);
+    assert_eq!(
+        stdout(&output),
+        "fun f() {\n\tif (true) {\n\t\tprint 1;\n\t}\n}\n"
+    );
+}
+
+#[test]
+fn stats_counts_statements_calls_and_scope_depth() {
+    let output = run_script(
+        "stats",
+        &["--stats"],
+        "fun f(n) { return n; }\nfor (var i = 0; i < 3; i = i + 1) f(i);\nprint clock() > 0;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "true\n");
+    // three calls to `f` and one to the `clock` native
+    assert_eq!(
+        stderr(&output),
+        "[stats] statements executed: 17\n[stats] calls made: 4\n[stats] max scope depth: 2\n"
+    );
+}
+
+#[test]
+fn panic_stops_the_program_with_its_message() {
+    let output = run_script(
+        "panic",
+        &["--no-color"],
+        "print 1;\nfun check(n) {\n  if (n < 0) panic(\"negative\");\n}\ncheck(1);\ncheck(-1);\nprint 2;\n",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        stdout(&output),
+        "1\nnegative\n[line 3]\n  in check() called from [line 6]\n"
+    );
+}
+
+#[test]
+fn nanotime_never_goes_backwards() {
+    let output = run_script(
+        "nanotime",
+        &[],
+        "var last = nanotime();\nvar ok = true;\nfor (var i = 0; i < 1000; i = i + 1) {\n  var now = nanotime();\n  if (now < last) ok = false;\n  last = now;\n}\nprint ok;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "true\n");
+}
+
+#[test]
+fn chr_and_ord_convert_between_characters_and_code_points() {
+    let output = run_script(
+        "chr_ord",
+        &[],
+        "print chr(65);\nprint ord(\"A\");\nprint chr(233);\nprint ord(chr(128512)) == 128512;\n",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "A\n65\né\ntrue\n");
+
+    for (call, message) in [
+        (
+            "chr(-1)",
+            "Invalid function arguments, 'chr' accepts a code point.",
+        ),
+        (
+            "chr(1.5)",
+            "Invalid function arguments, 'chr' accepts a code point.",
+        ),
+        ("chr(55296)", "Invalid code point."),
+        ("chr(1114112)", "Invalid code point."),
+        (
+            "chr(\"A\")",
+            "Invalid function arguments, 'chr' accepts a code point.",
+        ),
+        (
+            "ord(\"\")",
+            "'ord' expects a string of exactly one character.",
+        ),
+        (
+            "ord(\"ab\")",
+            "'ord' expects a string of exactly one character.",
+        ),
+        (
+            "ord(1)",
+            "Invalid function arguments, 'ord' accepts a single string.",
+        ),
+    ] {
+        let output = run_script(
+            "chr_ord_error",
+            &["--no-color"],
+            &format!("print {call};\n"),
+        );
+        assert_eq!(output.status.code(), Some(70), "{call}");
+        assert_eq!(stdout(&output), format!("{message}\n[line 1]\n"), "{call}");
+    }
+}